@@ -1,13 +1,27 @@
 use std::time::Instant;
 
 use bevy::{
-    color::palettes::css::MAGENTA, core_pipeline::tonemapping::Tonemapping, mesh::PlaneMeshBuilder,
-    prelude::*, window::PrimaryWindow,
+    asset::RenderAssetUsages,
+    core_pipeline::tonemapping::Tonemapping,
+    mesh::PlaneMeshBuilder,
+    prelude::*,
+    render::render_resource::{
+        AddressMode, BlendState, CompareFunction, DepthBiasState, Extent3d, Face, TextureDimension,
+        TextureFormat,
+    },
+    window::PrimaryWindow,
 };
 use glaciers::{
-    GlaciersParams,
-    canvas::{Triangle, Vertex},
+    GlaciersContext, GlaciersParams,
+    canvas::DrawState,
+    clip::ClipVertex,
+    depth::DepthState,
+    line::{LineCap, LineStyle},
     plugin::GlaciersPlugin,
+    shader::{Fragment, FragmentShader, MeshUniforms, MeshVertex, VertexShader},
+    texture::{Sampler, Texture, generate_mipmaps},
+    tonemapping::TonemapOperator,
+    varyings::Varyings,
 };
 
 pub const BLACK: Srgba = Srgba::rgb(0.0, 0.0, 0.0);
@@ -33,7 +47,12 @@ fn setup(
 ) {
     let scale = 1.0;
     let res = window.single().unwrap().resolution.clone();
-    let glaciers_context = glaciers_params.init_context(res, scale);
+    // The lighting goes above 1 so it's drawn in hdr and tonemapped
+    let mut glaciers_context =
+        glaciers_params.init_context(res, scale, Msaa::Sample4, TextureFormat::Rgba16Float);
+    glaciers_context.tonemapping = TonemapOperator::AgX;
+
+    commands.insert_resource(Checkerboard(checkerboard_image()));
 
     // camera
     commands.spawn((
@@ -62,12 +81,14 @@ fn setup(
             ),
         ),
         Transform::default(),
+        DoubleSided,
     ));
 }
 
 fn handle_input(
     keyboard: Res<ButtonInput<KeyCode>>,
     mut camera: Query<&mut Transform, With<Camera>>,
+    mut context: Query<&mut GlaciersContext>,
     time: Res<Time>,
 ) {
     // Exit
@@ -75,6 +96,19 @@ fn handle_input(
         std::process::exit(1);
     }
 
+    // Cycle through the tonemapping operators
+    if keyboard.just_pressed(KeyCode::KeyT) {
+        for mut context in &mut context {
+            context.tonemapping = match context.tonemapping {
+                TonemapOperator::None => TonemapOperator::Reinhard,
+                TonemapOperator::Reinhard => TonemapOperator::AcesFitted,
+                TonemapOperator::AcesFitted => TonemapOperator::AgX,
+                TonemapOperator::AgX => TonemapOperator::None,
+            };
+            info!("Tonemapping: {:?}", context.tonemapping);
+        }
+    }
+
     // Camera controller
     let speed = 5.0;
     let rotation_speed = speed * 2.0;
@@ -108,81 +142,75 @@ fn handle_input(
 fn draw(
     mut glaciers_params: GlaciersParams,
     mut window: Query<&mut Window, With<PrimaryWindow>>,
-    meshes: Query<(&Mesh3d, &GlobalTransform)>,
+    meshes: Query<(&Mesh3d, &GlobalTransform, Has<DoubleSided>)>,
     meshes_assets: Res<Assets<Mesh>>,
     views: Query<(&Camera, &GlobalTransform)>,
+    checkerboard: Res<Checkerboard>,
 ) -> Result<()> {
-    let scale = glaciers_params.context().scale;
     let mut canvas = glaciers_params.canvas();
 
+    let Some(texture) = Texture::new(&checkerboard.0) else {
+        return Ok(());
+    };
+    let fragment_shader = TexturedFragmentShader {
+        texture,
+        sampler: Sampler::LINEAR.with_address_mode(AddressMode::Repeat),
+    };
+
     let Ok((camera, global_camera)) = views.single() else {
         return Ok(());
     };
 
     let start = Instant::now();
 
+    // Bevy uses reversed depth, 0.0 is the far plane
+    canvas.set_depth_clear_value(0.0);
     canvas.clear();
 
-    for (mesh_3d, transform) in &meshes {
-        let Some(mesh) = meshes_assets.get(mesh_3d.id()) else {
-            warn!("Missing mesh asset");
-            continue;
+    for (mesh_3d, transform, double_sided) in &meshes {
+        let draw_state = DrawState {
+            depth: DepthState {
+                compare: CompareFunction::Greater,
+                write_enabled: true,
+                ..default()
+            },
+            cull_mode: if double_sided { None } else { Some(Face::Back) },
+            ..default()
         };
-        let Some(pos) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
-            warn!("Missing vertex attribute position");
-            continue;
+        // The edges are pulled towards the camera so they are only hidden by other triangles
+        let wireframe_state = DrawState {
+            depth: DepthState {
+                compare: CompareFunction::Greater,
+                write_enabled: false,
+                bias: DepthBiasState {
+                    constant: 0,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            },
+            blend: Some(BlendState::ALPHA_BLENDING),
+            ..draw_state
         };
-        let Some(pos) = pos.as_float3() else {
-            warn!("Failed to convert pos to float3");
+        let Some(mesh) = meshes_assets.get(mesh_3d.id()) else {
+            warn!("Missing mesh asset");
             continue;
         };
-        if let Some(indices) = mesh.indices() {
-            let mut primitive_id = 0;
-
-            let mut iter = indices.iter().peekable();
-            'outer: while iter.peek().is_some() {
-                let tri_indices = [
-                    iter.next().unwrap(),
-                    iter.next().unwrap(),
-                    iter.next().unwrap(),
-                ];
-                fastrand::seed(primitive_id);
-                let color = Color::srgba(fastrand::f32(), fastrand::f32(), fastrand::f32(), 1.0);
-                let mut vertices = [Vertex::new(Vec3::ZERO, MAGENTA.into()); 3];
-                for (i, &tri_i) in tri_indices.iter().enumerate() {
-                    let pos: Vec3 = pos[tri_i].into();
-                    let pos = transform.transform_point(pos);
-                    let view_pos = match camera.world_to_viewport_with_depth(global_camera, pos) {
-                        Ok(view_pos) => view_pos,
-                        Err(err) => {
-                            warn!("Triangle needs to be clipped. {err:?}");
-                            continue 'outer;
-                        }
-                    };
-                    let view_pos = view_pos * scale;
-                    if view_pos.y < 0.0
-                        || view_pos.y > canvas.size_f32().y
-                        || view_pos.x < 0.0
-                        || view_pos.x > canvas.size_f32().x
-                    {
-                        continue 'outer;
-                    }
-                    vertices[i] = Vertex::new(view_pos, color);
-                }
-                let triangle = Triangle::new(vertices);
-                canvas.draw_triangle_wide(&triangle);
-                canvas.draw_triangle_wireframe(&triangle, BLACK.to_u8_array());
-
-                primitive_id += 1;
-            }
-        } else {
-            for x in pos.chunks(3) {
-                let &[_pos0, _pos1, _pos2] = x else {
-                    unreachable!()
-                };
-                unimplemented!()
-            }
-        }
+        let uniforms = MeshUniforms::new(camera, global_camera, transform);
+        canvas.draw_mesh(
+            mesh,
+            &uniforms,
+            &draw_state,
+            &LitVertexShader,
+            &fragment_shader,
+        );
+        canvas.draw_mesh_wireframe(
+            mesh,
+            &uniforms,
+            BLACK.with_alpha(0.5).into(),
+            &LineStyle::new(1.0, LineCap::Round),
+            &wireframe_state,
+            &LitVertexShader,
+        );
     }
 
     let frame_time = start.elapsed().as_secs_f32() * 1000.0;
@@ -199,9 +227,89 @@ fn draw(
     Ok(())
 }
 
+/// Texture sampled by the fragment shader. It's stored outside of the image assets because the
+/// canvas borrows them mutably.
+#[derive(Resource)]
+struct Checkerboard(Image);
+
+fn checkerboard_image() -> Image {
+    let size = 64;
+    let square_size = 8;
+    let mut data = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            let color = if (x / square_size + y / square_size) % 2 == 0 {
+                WHITE
+            } else {
+                BLUE
+            };
+            data.extend_from_slice(&color.to_u8_array());
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD,
+    );
+    // Without mips the checkerboard aliases when it's far from the camera
+    generate_mipmaps(&mut image);
+    image
+}
+
+/// Gouraud shading with a single directional light. The varyings are the light color followed
+/// by the uv.
+struct LitVertexShader;
+
+impl VertexShader for LitVertexShader {
+    type Uniforms = MeshUniforms;
+
+    fn shade(&self, vertex: &MeshVertex, uniforms: &MeshUniforms) -> ClipVertex {
+        let light_dir = Vec3::new(0.5, 1.0, 0.3).normalize();
+        let world_pos = uniforms
+            .world_from_local
+            .transform_point3(vertex.position());
+        // The meshes aren't scaled so the normals don't need the inverse transpose
+        let normal = uniforms
+            .world_from_local
+            .transform_vector3(vertex.normal().unwrap_or(Vec3::Y))
+            .normalize();
+        let light = 0.2 + 2.8 * normal.dot(light_dir).max(0.0);
+        let mut varyings = Varyings::from_color(Color::linear_rgb(light, light, light));
+        varyings.extend_from_slice(&vertex.uv().unwrap_or_default().to_array());
+        ClipVertex::with_varyings(uniforms.clip_from_world * world_pos.extend(1.0), varyings)
+    }
+}
+
+/// Modulates the light color with the texture
+struct TexturedFragmentShader<'a> {
+    texture: Texture<'a>,
+    sampler: Sampler,
+}
+
+impl FragmentShader for TexturedFragmentShader<'_> {
+    fn shade(&self, fragment: &Fragment) -> Option<Vec4> {
+        let light = fragment.varyings.vec4(0);
+        let uv_scale = 4.0;
+        let uv = fragment.varyings.vec2(4) * uv_scale;
+        let ddx = fragment.ddx.vec2(4) * uv_scale;
+        let ddy = fragment.ddy.vec2(4) * uv_scale;
+        Some(light * self.texture.sample_grad(&self.sampler, uv, ddx, ddy))
+    }
+}
+
 #[derive(Component)]
 struct Rotates;
 
+/// Disables backface culling for the mesh
+#[derive(Component)]
+struct DoubleSided;
+
 /// Rotates any entity around the x and z axis
 fn rotate(time: Res<Time>, mut query: Query<&mut Transform, With<Rotates>>) {
     let speed = 1.5;
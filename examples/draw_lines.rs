@@ -1,12 +1,13 @@
 use std::time::{Duration, Instant};
 
 use bevy::{
-    asset::RenderAssetUsages, core_pipeline::tonemapping::Tonemapping, prelude::*,
-    render::render_resource::*, window::PrimaryWindow,
+    core_pipeline::tonemapping::Tonemapping, prelude::*, render::render_resource::TextureFormat,
+    window::PrimaryWindow,
 };
 use glaciers::{
-    GlaciersContext, GlaciersParams,
-    canvas::{Triangle, Vertex},
+    GlaciersParams,
+    canvas::{DrawState, Triangle, Vertex},
+    line::{LineCap, LineStyle},
     plugin::GlaciersPlugin,
 };
 
@@ -29,24 +30,13 @@ fn main() {
 
 fn setup(
     mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
+    mut glaciers_params: GlaciersParams,
     window: Query<&Window, With<PrimaryWindow>>,
 ) {
-    let res = &window.single().unwrap().resolution;
     let scale = 0.15;
-
-    let image_size = Vec2::new(res.width() * scale, res.height() * scale).as_uvec2();
-    let image = Image::new_fill(
-        Extent3d {
-            width: image_size.x,
-            height: image_size.y,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &[0u8; 4],
-        TextureFormat::Rgba8UnormSrgb,
-        RenderAssetUsages::all(),
-    );
+    let res = window.single().unwrap().resolution.clone();
+    let glaciers_context =
+        glaciers_params.init_context(res, scale, Msaa::Off, TextureFormat::Rgba8UnormSrgb);
 
     // camera
     commands.spawn((
@@ -57,11 +47,7 @@ fn setup(
             ..default()
         },
         Tonemapping::None,
-        GlaciersContext {
-            image: images.add(image),
-            scale,
-            ..default()
-        },
+        glaciers_context,
     ));
 }
 
@@ -102,19 +88,37 @@ fn draw(
     canvas.draw_line(
         Vec3::new(0.0, half_height, 0.0),
         Vec3::new(image_size.x as f32, half_height, 0.0),
-        [0xff, 0, 0, 1],
+        RED.into(),
+        &DrawState::OVERLAY,
     );
     canvas.draw_line(
         Vec3::new(half_width, 0.0, 0.0),
         Vec3::new(half_width, image_size.y as f32, 0.0),
-        [0, 0xff, 0, 1],
+        GREEN.into(),
+        &DrawState::OVERLAY,
     );
     canvas.draw_line(
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(image_size.x as f32, image_size.y as f32, 0.0),
-        [0, 0, 0xff, 1],
+        BLUE.into(),
+        &DrawState::OVERLAY,
     );
 
+    // Anti-aliased lines with every cap style
+    for (i, cap) in [LineCap::Butt, LineCap::Square, LineCap::Round]
+        .into_iter()
+        .enumerate()
+    {
+        let y = half_height * 0.25 * (i + 1) as f32;
+        canvas.draw_line_aa(
+            Vec3::new(half_width * 0.25, y, 0.0),
+            Vec3::new(half_width * 0.75, y + half_height * 0.1, 0.0),
+            WHITE.into(),
+            &LineStyle::new(3.0, cap),
+            &DrawState::OVERLAY,
+        );
+    }
+
     let frame_time = start.elapsed().as_secs_f32() * 1000.0;
     let fps = 1000.0 / frame_time;
     if let Some(timer) = timer.as_ref()
@@ -9,14 +9,18 @@ use bevy::{
         theme::{ThemeBackgroundColor, ThemedText, UiTheme},
     },
     prelude::*,
+    render::render_resource::TextureFormat,
     ui::Checked,
     ui_widgets::{ValueChange, observe},
     window::PrimaryWindow,
 };
 use glaciers::{
-    GlaciersParams,
-    canvas::{Triangle, Vertex},
+    GlaciersContext, GlaciersParams,
+    canvas::{DrawState, Triangle, Vertex},
+    depth::DepthState,
     plugin::GlaciersPlugin,
+    rasterizer::RasterizerStrategy,
+    shader::VertexColor,
 };
 
 pub const BLACK: Srgba = Srgba::rgb(0.0, 0.0, 0.0);
@@ -26,20 +30,13 @@ pub const RED: Srgba = Srgba::rgb(1.0, 0.0, 0.0);
 pub const GREEN: Srgba = Srgba::rgb(0.0, 1.0, 0.0);
 pub const BLUE: Srgba = Srgba::rgb(0.0, 0.0, 1.0);
 
-pub const USE_WIDE: bool = true;
-pub const USE_BOX: bool = true;
-
 pub const TRIANGLE_COUNT: usize = 1000;
 
 fn main() {
     App::new()
         .add_plugins((DefaultPlugins, GlaciersPlugin, FeathersPlugins))
         .insert_resource(UiTheme(create_dark_theme()))
-        .insert_resource(GlobalConfigs {
-            use_wide: true,
-            use_box: true,
-            _show_box_outline: true,
-        })
+        .insert_resource(GlobalConfigs { use_parallel: true })
         .add_systems(Startup, setup)
         .add_systems(Update, (rotate, handle_input, draw))
         .run();
@@ -52,7 +49,8 @@ fn setup(
 ) {
     let scale = 1.0;
     let res = window.single().unwrap().resolution.clone();
-    let glaciers_context = glaciers_params.init_context(res, scale);
+    let glaciers_context =
+        glaciers_params.init_context(res, scale, Msaa::Off, TextureFormat::Rgba8UnormSrgb);
     let image_size = glaciers_context.image_size;
 
     // camera
@@ -98,7 +96,7 @@ fn setup(
             Vertex::new(pos_b, random_color),
             Vertex::new(pos_c, random_color),
         ]);
-        if tri.is_visible() {
+        if tri.is_visible(&DrawState::default()) {
             commands.spawn(tri);
             count += 1;
             if count == TRIANGLE_COUNT {
@@ -111,10 +109,7 @@ fn setup(
 
 #[derive(Resource)]
 struct GlobalConfigs {
-    use_wide: bool,
-    use_box: bool,
-    // TODO
-    _show_box_outline: bool,
+    use_parallel: bool,
 }
 
 fn spawn_ui_root(commands: &mut Commands) {
@@ -131,49 +126,45 @@ fn spawn_ui_root(commands: &mut Commands) {
             min_width: px(100),
             ..Default::default()
         },
-        children![
-            (
-                checkbox(Checked, Spawn((Text::new("Use wide"), ThemedText))),
-                observe(
-                    |change: On<ValueChange<bool>>,
-                     mut commands: Commands,
-                     mut configs: ResMut<GlobalConfigs>| {
-                        configs.use_wide = change.value;
-                        let mut checkbox = commands.entity(change.source);
-                        if change.value {
-                            checkbox.insert(Checked);
-                        } else {
-                            checkbox.remove::<Checked>();
-                        }
-                    }
-                )
-            ),
-            (
-                checkbox(Checked, Spawn((Text::new("Use box"), ThemedText))),
-                observe(
-                    |change: On<ValueChange<bool>>,
-                     mut commands: Commands,
-                     mut configs: ResMut<GlobalConfigs>| {
-                        configs.use_box = change.value;
-                        let mut checkbox = commands.entity(change.source);
-                        if change.value {
-                            checkbox.insert(Checked);
-                        } else {
-                            checkbox.remove::<Checked>();
-                        }
+        children![(
+            checkbox(Checked, Spawn((Text::new("Use parallel"), ThemedText))),
+            observe(
+                |change: On<ValueChange<bool>>,
+                 mut commands: Commands,
+                 mut configs: ResMut<GlobalConfigs>| {
+                    configs.use_parallel = change.value;
+                    let mut checkbox = commands.entity(change.source);
+                    if change.value {
+                        checkbox.insert(Checked);
+                    } else {
+                        checkbox.remove::<Checked>();
                     }
-                )
-            ),
-        ],
+                }
+            )
+        )],
     );
     commands.spawn(root);
 }
 
-fn handle_input(keyboard: Res<ButtonInput<KeyCode>>) {
+fn handle_input(keyboard: Res<ButtonInput<KeyCode>>, mut context: Query<&mut GlaciersContext>) {
     // Exit
     if keyboard.just_pressed(KeyCode::Escape) {
         std::process::exit(1);
     }
+
+    // Cycle through the rasterizers used when not drawing in parallel
+    if keyboard.just_pressed(KeyCode::KeyR) {
+        for mut context in &mut context {
+            context.rasterizer = match context.rasterizer {
+                RasterizerStrategy::Auto => RasterizerStrategy::Scalar,
+                RasterizerStrategy::Scalar => RasterizerStrategy::ScalarBox,
+                RasterizerStrategy::ScalarBox => RasterizerStrategy::Wide,
+                RasterizerStrategy::Wide => RasterizerStrategy::WideBox,
+                RasterizerStrategy::WideBox => RasterizerStrategy::Auto,
+            };
+            info!("Rasterizer: {:?}", context.rasterizer);
+        }
+    }
 }
 
 fn draw(
@@ -203,19 +194,18 @@ fn draw(
     {
         let _draw_triangle_span = info_span!("draw_triangle").entered();
 
-        for triangle in &triangles {
-            if global_configs.use_wide {
-                if global_configs.use_box {
-                    canvas.draw_triangle_wide_box(triangle, false);
-                } else {
-                    canvas.draw_triangle_wide(triangle);
-                }
-            } else {
-                if global_configs.use_box {
-                    canvas.draw_triangle_box(triangle, false);
-                } else {
-                    canvas.draw_triangle(triangle);
-                }
+        // All the triangles are at the same depth, draw them in submission order
+        let draw_state = DrawState {
+            depth: DepthState::DISABLED,
+            ..default()
+        };
+
+        if global_configs.use_parallel {
+            let triangles: Vec<Triangle> = triangles.iter().copied().collect();
+            canvas.draw_triangles_parallel(&triangles, &draw_state, &VertexColor);
+        } else {
+            for triangle in &triangles {
+                canvas.draw_triangle(triangle, &draw_state, &VertexColor);
             }
         }
     }
@@ -228,12 +218,17 @@ fn draw(
         let _update_title_span = info_span!("update_window_title").entered();
 
         window.single_mut().unwrap().title = format!(
-            "Glaciers - {}x{} {:.2}ms {:.0}fps - {} triangles",
+            "Glaciers - {}x{} {:.2}ms {:.0}fps - {} triangles - {}",
             canvas.size().x,
             canvas.size().y,
             frame_time,
             fps,
-            triangles.count()
+            triangles.count(),
+            if global_configs.use_parallel {
+                "Parallel".to_string()
+            } else {
+                format!("{:?}", canvas.rasterizer())
+            }
         );
     }
     // info!("-- end --");
@@ -7,14 +7,17 @@ use bevy::{
         theme::{ThemeBackgroundColor, ThemedText, UiTheme},
     },
     prelude::*,
+    render::render_resource::TextureFormat,
     ui::Checked,
     ui_widgets::{SliderPrecision, SliderStep, SliderValue, ValueChange, observe},
     window::{PrimaryWindow, WindowResolution},
 };
 use glaciers::{
-    GlaciersParams,
-    canvas::{Triangle, Vertex},
+    GlaciersContext, GlaciersParams,
+    canvas::{DrawState, Triangle, Vertex},
     plugin::GlaciersPlugin,
+    rasterizer::RasterizerStrategy,
+    shader::VertexColor,
 };
 
 pub const BLACK: Srgba = Srgba::rgb(0.0, 0.0, 0.0);
@@ -38,13 +41,8 @@ fn main() {
             GlaciersPlugin,
         ))
         .insert_resource(UiTheme(create_dark_theme()))
-        .insert_resource(GlobalConfigs {
-            use_wide: true,
-            use_box: true,
-            show_box_outline: true,
-        })
         .add_systems(Startup, setup)
-        .add_systems(Update, (exit_on_esc, draw))
+        .add_systems(Update, (handle_input, draw))
         .run();
 }
 
@@ -55,7 +53,10 @@ fn setup(
 ) {
     let scale = 0.25;
     let res = window.single().unwrap().resolution.clone();
-    let glaciers_context = glaciers_params.init_context(res, scale);
+    let mut glaciers_context =
+        glaciers_params.init_context(res, scale, Msaa::Sample4, TextureFormat::Rgba8UnormSrgb);
+    glaciers_context.rasterizer = RasterizerStrategy::WideBox;
+    glaciers_context.show_box_outlines = true;
     let image_size = glaciers_context.image_size;
 
     // camera
@@ -107,13 +108,6 @@ fn setup(
     );
 }
 
-#[derive(Resource)]
-struct GlobalConfigs {
-    use_wide: bool,
-    use_box: bool,
-    show_box_outline: bool,
-}
-
 fn spawn_ui_root(commands: &mut Commands, max_width: f32, max_height: f32, triangle: &Triangle) {
     let root = (
         ThemeBackgroundColor(bevy::feathers::tokens::WINDOW_BG),
@@ -129,45 +123,15 @@ fn spawn_ui_root(commands: &mut Commands, max_width: f32, max_height: f32, trian
             ..Default::default()
         },
         children![
-            (
-                checkbox(Checked, Spawn((Text::new("Use wide"), ThemedText))),
-                observe(
-                    |change: On<ValueChange<bool>>,
-                     mut commands: Commands,
-                     mut configs: ResMut<GlobalConfigs>| {
-                        configs.use_wide = change.value;
-                        let mut checkbox = commands.entity(change.source);
-                        if change.value {
-                            checkbox.insert(Checked);
-                        } else {
-                            checkbox.remove::<Checked>();
-                        }
-                    }
-                )
-            ),
-            (
-                checkbox(Checked, Spawn((Text::new("Use box"), ThemedText))),
-                observe(
-                    |change: On<ValueChange<bool>>,
-                     mut commands: Commands,
-                     mut configs: ResMut<GlobalConfigs>| {
-                        configs.use_box = change.value;
-                        let mut checkbox = commands.entity(change.source);
-                        if change.value {
-                            checkbox.insert(Checked);
-                        } else {
-                            checkbox.remove::<Checked>();
-                        }
-                    }
-                )
-            ),
             (
                 checkbox(Checked, Spawn((Text::new("Show outline"), ThemedText))),
                 observe(
                     |change: On<ValueChange<bool>>,
                      mut commands: Commands,
-                     mut configs: ResMut<GlobalConfigs>| {
-                        configs.show_box_outline = change.value;
+                     mut contexts: Query<&mut GlaciersContext>| {
+                        for mut context in &mut contexts {
+                            context.show_box_outlines = change.value;
+                        }
                         let mut checkbox = commands.entity(change.source);
                         if change.value {
                             checkbox.insert(Checked);
@@ -276,33 +240,33 @@ fn labelled_slider(label: &str, max: f32, value: f32, b: impl Bundle) -> impl Bu
     )
 }
 
-fn exit_on_esc(keyboard: Res<ButtonInput<KeyCode>>) {
+fn handle_input(keyboard: Res<ButtonInput<KeyCode>>, mut context: Query<&mut GlaciersContext>) {
     if keyboard.just_pressed(KeyCode::Escape) {
         std::process::exit(1);
     }
+
+    // Cycle through the rasterizers
+    if keyboard.just_pressed(KeyCode::KeyR) {
+        for mut context in &mut context {
+            context.rasterizer = match context.rasterizer {
+                RasterizerStrategy::Auto => RasterizerStrategy::Scalar,
+                RasterizerStrategy::Scalar => RasterizerStrategy::ScalarBox,
+                RasterizerStrategy::ScalarBox => RasterizerStrategy::Wide,
+                RasterizerStrategy::Wide => RasterizerStrategy::WideBox,
+                RasterizerStrategy::WideBox => RasterizerStrategy::Auto,
+            };
+            info!("Rasterizer: {:?}", context.rasterizer);
+        }
+    }
 }
 
-fn draw(
-    mut glaciers_params: GlaciersParams,
-    triangle: Single<&Triangle>,
-    configs: Res<GlobalConfigs>,
-) -> Result<()> {
+fn draw(mut glaciers_params: GlaciersParams, triangle: Single<&Triangle>) -> Result<()> {
     let mut canvas = glaciers_params.canvas();
     canvas.clear();
 
-    if configs.use_wide {
-        if configs.use_box {
-            canvas.draw_triangle_wide_box(&triangle, configs.show_box_outline);
-        } else {
-            canvas.draw_triangle_wide(&triangle);
-        }
-    } else {
-        if configs.use_box {
-            canvas.draw_triangle_box(&triangle, configs.show_box_outline);
-        } else {
-            canvas.draw_triangle(&triangle);
-        }
-    }
+    let draw_state = DrawState::default();
+
+    canvas.draw_triangle(&triangle, &draw_state, &VertexColor);
 
     Ok(())
 }
@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+
+use crate::{
+    canvas::{DrawState, GlaciersCanvas, Triangle, TriangleSetup, Vertex},
+    shader::FragmentShader,
+};
+
+impl GlaciersCanvas<'_> {
+    /// Draws triangles in canvas space with the wide rasterizer, in order.
+    ///
+    /// Unlike calling [`GlaciersCanvas::draw_triangle_wide`] for each triangle, culling, snapping
+    /// and the edge setup run for 8 triangles at a time.
+    pub fn draw_triangles(
+        &mut self,
+        triangles: &[Triangle],
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        let _draw_triangles_span = info_span!("draw_triangles").entered();
+        for chunk in triangles.chunks(8) {
+            let setups = TriangleSetup::new_x8(self, chunk, state);
+            for setup in setups.iter().flatten() {
+                self.rasterize_wide(setup, state, shader);
+            }
+        }
+    }
+
+    /// Draws a triangle list in canvas space made of the `vertices` at every 3 `indices`, see
+    /// [`GlaciersCanvas::draw_triangles`].
+    ///
+    /// Triangles using out of bounds vertices are discarded, as are the last indices if they
+    /// don't make a whole triangle.
+    pub fn draw_indexed(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        let _draw_indexed_span = info_span!("draw_indexed").entered();
+        let mut triangles = Vec::with_capacity(8);
+        for chunk in indices.chunks(3 * 8) {
+            triangles.clear();
+            triangles.extend(chunk.chunks_exact(3).filter_map(|triangle| {
+                let [Some(a), Some(b), Some(c)] =
+                    [0, 1, 2].map(|i| vertices.get(triangle[i] as usize).copied())
+                else {
+                    return None;
+                };
+                Some(Triangle::new([a, b, c]))
+            }));
+            self.draw_triangles(&triangles, state, shader);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Blend states that wgpu doesn't provide. Use [`BlendState::ALPHA_BLENDING`] and
+//! [`BlendState::PREMULTIPLIED_ALPHA_BLENDING`] for the usual alpha blending.
+
+use bevy::prelude::*;
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+/// Adds the color to the canvas, useful for particles and lights
+pub const ADDITIVE: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// Multiplies the canvas by the color
+pub const MULTIPLY: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Dst,
+        dst_factor: BlendFactor::Zero,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::DstAlpha,
+        dst_factor: BlendFactor::Zero,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// Keeps the smallest value of each channel
+pub const MIN: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Min,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Min,
+    },
+};
+
+/// Keeps the biggest value of each channel
+pub const MAX: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Max,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Max,
+    },
+};
+
+/// Blends the linear `src` color output by a fragment with the linear `dst` color already in the
+/// canvas, following the wgpu rules.
+///
+/// Dual source blending isn't supported, the `Src1` factors behave like [`BlendFactor::Zero`].
+#[inline(always)]
+pub(crate) fn blend(state: &BlendState, src: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+    let color = blend_component(&state.color, src, dst, constant);
+    let alpha = blend_component(&state.alpha, src, dst, constant);
+    color.xyz().extend(alpha.w)
+}
+
+#[inline(always)]
+fn blend_component(component: &BlendComponent, src: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+    let src_term = || src * factor(component.src_factor, src, dst, constant);
+    let dst_term = || dst * factor(component.dst_factor, src, dst, constant);
+    match component.operation {
+        BlendOperation::Add => src_term() + dst_term(),
+        BlendOperation::Subtract => src_term() - dst_term(),
+        BlendOperation::ReverseSubtract => dst_term() - src_term(),
+        // The factors are ignored
+        BlendOperation::Min => src.min(dst),
+        BlendOperation::Max => src.max(dst),
+    }
+}
+
+#[inline(always)]
+fn factor(factor: BlendFactor, src: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+    match factor {
+        BlendFactor::Zero => Vec4::ZERO,
+        BlendFactor::One => Vec4::ONE,
+        BlendFactor::Src => src,
+        BlendFactor::OneMinusSrc => Vec4::ONE - src,
+        BlendFactor::SrcAlpha => Vec4::splat(src.w),
+        BlendFactor::OneMinusSrcAlpha => Vec4::splat(1.0 - src.w),
+        BlendFactor::Dst => dst,
+        BlendFactor::OneMinusDst => Vec4::ONE - dst,
+        BlendFactor::DstAlpha => Vec4::splat(dst.w),
+        BlendFactor::OneMinusDstAlpha => Vec4::splat(1.0 - dst.w),
+        BlendFactor::SrcAlphaSaturated => Vec3::splat(src.w.min(1.0 - dst.w)).extend(1.0),
+        BlendFactor::Constant => constant,
+        BlendFactor::OneMinusConstant => Vec4::ONE - constant,
+        BlendFactor::Src1
+        | BlendFactor::OneMinusSrc1
+        | BlendFactor::Src1Alpha
+        | BlendFactor::OneMinusSrc1Alpha => Vec4::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: Vec4 = Vec4::new(0.8, 0.4, 0.2, 0.25);
+    const DST: Vec4 = Vec4::new(0.2, 0.6, 1.0, 0.5);
+
+    fn assert_blends(state: BlendState, constant: Vec4, expected: Vec4) {
+        let color = blend(&state, SRC, DST, constant);
+        assert!(color.abs_diff_eq(expected, 1e-6), "{state:?} {color}");
+    }
+
+    #[test]
+    fn blend_states() {
+        assert_blends(BlendState::REPLACE, Vec4::ZERO, SRC);
+        assert_blends(
+            BlendState::ALPHA_BLENDING,
+            Vec4::ZERO,
+            (SRC.xyz() * 0.25 + DST.xyz() * 0.75).extend(0.25 + 0.5 * 0.75),
+        );
+        assert_blends(
+            BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            Vec4::ZERO,
+            SRC + DST * 0.75,
+        );
+        assert_blends(ADDITIVE, Vec4::ZERO, SRC + DST);
+        assert_blends(MULTIPLY, Vec4::ZERO, SRC * DST);
+        assert_blends(MIN, Vec4::ZERO, SRC.min(DST));
+        assert_blends(MAX, Vec4::ZERO, SRC.max(DST));
+    }
+
+    #[test]
+    fn factors_and_operations() {
+        use BlendFactor::*;
+        use BlendOperation::*;
+
+        let state = |src_factor, dst_factor, operation| {
+            let component = BlendComponent {
+                src_factor,
+                dst_factor,
+                operation,
+            };
+            BlendState {
+                color: component,
+                alpha: component,
+            }
+        };
+        assert_blends(state(One, One, Subtract), Vec4::ZERO, SRC - DST);
+        assert_blends(state(One, One, ReverseSubtract), Vec4::ZERO, DST - SRC);
+        assert_blends(
+            state(Src, OneMinusDst, Add),
+            Vec4::ZERO,
+            SRC * SRC + DST * (1.0 - DST),
+        );
+        assert_blends(
+            state(OneMinusSrc, Dst, Add),
+            Vec4::ZERO,
+            SRC * (1.0 - SRC) + DST * DST,
+        );
+        assert_blends(
+            state(DstAlpha, OneMinusDstAlpha, Add),
+            Vec4::ZERO,
+            SRC * 0.5 + DST * 0.5,
+        );
+        let constant = Vec4::new(0.5, 0.25, 0.125, 1.0);
+        assert_blends(
+            state(Constant, OneMinusConstant, Add),
+            constant,
+            SRC * constant + DST * (1.0 - constant),
+        );
+        // Saturated by the alpha of the destination, except for the alpha itself
+        assert_blends(
+            state(SrcAlphaSaturated, Zero, Add),
+            Vec4::ZERO,
+            (SRC.xyz() * 0.25).extend(SRC.w),
+        );
+        // The factors of min and max are ignored
+        assert_blends(state(Zero, Zero, Min), Vec4::ZERO, SRC.min(DST));
+        assert_blends(state(Zero, Zero, Max), Vec4::ZERO, SRC.max(DST));
+        // Dual source blending isn't supported
+        assert_blends(state(Src1, One, Add), Vec4::ZERO, DST);
+    }
+}
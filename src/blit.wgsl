@@ -0,0 +1,33 @@
+// Copies the canvas image to the view with a fullscreen triangle, see `CanvasBlitter`
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4<f32>(out.uv * 2.0 - 1.0, 0.0, 1.0);
+    // The canvas is y down
+    out.uv.y = 1.0 - out.uv.y;
+    return out;
+}
+
+@group(0) @binding(0)
+var canvas: texture_2d<f32>;
+@group(0) @binding(1)
+var canvas_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(canvas, canvas_sampler, in.uv);
+}
+
+// Single channel canvases are sampled as red, they are shown in grey instead
+@fragment
+fn fs_grey(in: VertexOutput) -> @location(0) vec4<f32> {
+    let value = textureSample(canvas, canvas_sampler, in.uv).r;
+    return vec4<f32>(value, value, value, 1.0);
+}
@@ -1,50 +1,617 @@
-use bevy::{color::palettes::css::MAGENTA, image::TextureFormatPixelInfo, prelude::*};
-use glam_wide::{CmpLe, Vec2x8, Vec3x8, boolf32x8, f32x8};
+use std::ops::Range;
+
+use bevy::{
+    camera::Viewport,
+    color::palettes::css::{LIME, RED},
+    math::{I64Vec2, I64Vec3},
+    prelude::*,
+};
+use glam_wide::{CmpEq, CmpGt, CmpLe, CmpLt, Vec2x8, Vec3x8, boolf32x8, f32x8};
+use wgpu::{BlendState, Face, FrontFace};
+
+use crate::{
+    blend::blend,
+    clip::clip_line,
+    depth::DepthState,
+    format::{MAX_PIXEL_SIZE, PixelFormat},
+    interpolation::{Interpolation, TriangleAttributes},
+    msaa::{MAX_SAMPLES, sample_positions},
+    rasterizer::{BOX_ORIENT, RasterizerStrategy},
+    shader::{Fragment, FragmentShader, FragmentX8},
+    tonemapping::TonemapOperator,
+    varyings::Varyings,
+};
+
+/// Fixed function state used by a single draw call, similar to a gpu pipeline descriptor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawState {
+    pub depth: DepthState,
+    /// Winding order of front facing triangles.
+    ///
+    /// Like wgpu, the winding is defined with the y axis pointing up, so meshes that render
+    /// correctly with bevy also render correctly here even though the canvas is y down.
+    pub front_face: FrontFace,
+    /// Which faces are discarded before rasterization. `None` draws both sides.
+    pub cull_mode: Option<Face>,
+    pub interpolation: Interpolation,
+    /// How colors are combined with the canvas. `None` replaces the canvas color.
+    ///
+    /// Blending is done in linear space, see [`crate::blend`] for more blend states.
+    pub blend: Option<BlendState>,
+    /// Linear rgba color used by [`wgpu::BlendFactor::Constant`]
+    pub blend_constant: Vec4,
+}
+
+impl DrawState {
+    pub const DEFAULT: Self = Self {
+        depth: DepthState::DEFAULT,
+        front_face: FrontFace::Ccw,
+        cull_mode: Some(Face::Back),
+        interpolation: Interpolation::Perspective,
+        blend: None,
+        blend_constant: Vec4::ZERO,
+    };
+
+    /// State used for debug overlays like the block outlines. Ignores the depth buffer.
+    pub const OVERLAY: Self = Self {
+        depth: DepthState::DISABLED,
+        cull_mode: None,
+        ..Self::DEFAULT
+    };
+}
+
+impl Default for DrawState {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Primitive being drawn, reported by out of bounds writes when
+/// [`GlaciersContext::assert_bounds`](crate::GlaciersContext::assert_bounds) is enabled
+#[derive(Debug, Clone, Copy)]
+pub enum Primitive {
+    None,
+    Point(IVec2),
+    Line(Vec3, Vec3),
+    Triangle([Vec3; 3]),
+}
 
 pub struct GlaciersCanvas<'a> {
-    pub(crate) color: &'a mut Image,
-    pub(crate) pixel_size: usize,
-    // depth: Image,
+    /// Pixels of the canvas image
+    pub(crate) color: &'a mut [u8],
+    /// Depth of every sample, see [`crate::depth::DepthBuffer`]
+    pub(crate) depth: &'a mut [f32],
+    /// Multisampled or high dynamic range color, drawn to instead of `color` when set. See
+    /// [`crate::msaa::SampleBuffer`].
+    pub(crate) samples: Option<&'a mut [u8]>,
+    /// Size of the whole canvas
+    pub(crate) size: UVec2,
+    /// Rows of the canvas stored in the buffers. It's every row except for the bands drawn in
+    /// parallel, see [`GlaciersCanvas::split_bands`].
+    pub(crate) rows: Range<u32>,
+    pub(crate) msaa: Msaa,
+    pub(crate) depth_clear_value: f32,
+    /// Sample positions relative to the pixel center, see [`crate::msaa::sample_positions`]
+    pub(crate) sample_offsets: [Vec2; MAX_SAMPLES],
+    /// Encoding of the pixels of `color`
+    pub(crate) format: PixelFormat,
+    /// Encoding of the pixels of `samples`
+    pub(crate) sample_format: PixelFormat,
+    /// Applied when resolving high dynamic range samples into `color`
+    pub(crate) tonemapping: TonemapOperator,
+    /// Exposure in stops applied before the tonemapping
+    pub(crate) exposure: f32,
+    /// Area normalized device coordinates are mapped to
+    pub(crate) viewport: Viewport,
+    /// Pixels outside of this rectangle are never written, always inside of the canvas
+    pub(crate) scissor: URect,
+    /// Used by [`GlaciersCanvas::draw_triangle`]
+    pub(crate) rasterizer: RasterizerStrategy,
+    /// Panic on out of bounds writes in debug builds instead of dropping them
+    pub(crate) assert_bounds: bool,
+    /// Passed to the box rasterizers by [`GlaciersCanvas::draw_triangle`]
+    pub(crate) show_box_outlines: bool,
+    pub(crate) primitive: Primitive,
+    /// Whether [`GlaciersCanvas::resolve`] is called on drop, false for the bands drawn in
+    /// parallel since the canvas they are split from resolves them
+    pub(crate) resolve_on_drop: bool,
 }
 
 impl<'a> GlaciersCanvas<'a> {
     pub fn size(&self) -> UVec2 {
-        self.color.size()
+        self.size
     }
 
     pub fn size_f32(&self) -> Vec2 {
-        self.color.size_f32()
+        self.size.as_vec2()
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        self.msaa
+    }
+
+    /// Returns a viewport covering the whole canvas with the full depth range
+    pub fn full_viewport(&self) -> Viewport {
+        Viewport {
+            physical_position: UVec2::ZERO,
+            physical_size: self.size(),
+            depth: 0.0..1.0,
+        }
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    /// Sets the area of the canvas normalized device coordinates are mapped to and the range
+    /// their depth is mapped to, like [`wgpu::RenderPass::set_viewport`]. `None` resets it to
+    /// [`GlaciersCanvas::full_viewport`].
+    ///
+    /// This only affects primitives going through the vertex stage like
+    /// [`GlaciersCanvas::draw_mesh`]. Use [`GlaciersCanvas::set_scissor`] to also restrict
+    /// drawing to the viewport.
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.viewport = viewport.unwrap_or_else(|| self.full_viewport());
+    }
+
+    pub fn scissor(&self) -> URect {
+        self.scissor
+    }
+
+    /// Restricts every draw to a rectangle of the canvas in pixels, like
+    /// [`wgpu::RenderPass::set_scissor_rect`]. `None` resets it to the whole canvas.
+    pub fn set_scissor(&mut self, scissor: Option<URect>) {
+        let canvas = URect::from_corners(UVec2::ZERO, self.size());
+        self.scissor = scissor.map_or(canvas, |scissor| scissor.intersect(canvas));
+    }
+
+    pub fn rasterizer(&self) -> RasterizerStrategy {
+        self.rasterizer
+    }
+
+    /// Changes the rasterizer used by [`GlaciersCanvas::draw_triangle`] for the next triangles,
+    /// it starts as [`GlaciersContext::rasterizer`](crate::GlaciersContext::rasterizer)
+    pub fn set_rasterizer(&mut self, rasterizer: RasterizerStrategy) {
+        self.rasterizer = rasterizer;
+    }
+
+    /// Area of the canvas primitives are clipped to, in pixels
+    pub(crate) fn bounds(&self) -> Rect {
+        self.scissor.as_rect()
+    }
+
+    /// Returns true if the pixel is inside of the canvas and the scissor rectangle
+    #[inline(always)]
+    pub fn contains(&self, pos: IVec2) -> bool {
+        pos.cmpge(self.scissor.min.as_ivec2()).all() && pos.cmplt(self.scissor.max.as_ivec2()).all()
+    }
+
+    /// Clamps the bounding box of a triangle to the pixels inside of the scissor rectangle,
+    /// `None` if they don't overlap or if the triangle is beyond [`MAX_COORDINATE`]
+    #[inline(always)]
+    fn clamp_aabb(&self, (min, max): (Vec3, Vec3)) -> Option<(Vec3, Vec3)> {
+        // Also false for NaN
+        let in_range = min.xy().cmpge(Vec2::splat(-MAX_COORDINATE)).all()
+            && max.xy().cmple(Vec2::splat(MAX_COORDINATE)).all();
+        if !in_range {
+            return None;
+        }
+        let scissor_min = self.scissor.min.as_vec2();
+        // The bounds are inclusive so the last pixel is used instead of the edge
+        let scissor_max = self.scissor.max.as_vec2() - 1.0;
+        // Compared in whole pixels since the samples of a pixel can be covered even when the
+        // bounding box ends before its center
+        let min = min.xy().floor().max(scissor_min).extend(min.z);
+        let max = max.xy().floor().min(scissor_max).extend(max.z);
+        (min.x <= max.x && min.y <= max.y).then_some((min, max))
+    }
+
+    /// Records the primitive reported by out of bounds writes, only when they are asserted
+    #[inline(always)]
+    pub(crate) fn begin_primitive(&mut self, primitive: Primitive) {
+        if cfg!(debug_assertions) && self.assert_bounds {
+            self.primitive = primitive;
+        }
+    }
+
+    /// Returns true if the pixel can be written. Writes outside of the canvas or the scissor
+    /// rectangle are dropped, or panic in debug builds when [`GlaciersContext::assert_bounds`]
+    /// is enabled.
+    ///
+    /// Every access to the buffers goes through it, see [`GlaciersCanvas::pixel_index`]. The
+    /// rasterizers clip their quads and lines to the scissor rectangle before, so it only fails
+    /// for points or because of a rasterizer bug.
+    ///
+    /// [`GlaciersContext::assert_bounds`]: crate::GlaciersContext::assert_bounds
+    #[inline(always)]
+    fn check_bounds(&self, pos: IVec2) -> bool {
+        let inside = self.contains(pos);
+        if cfg!(debug_assertions) && self.assert_bounds && !inside {
+            panic!(
+                "Out of bounds write at {pos} outside of {:?} while drawing {:?}",
+                self.scissor, self.primitive
+            );
+        }
+        inside
     }
 
     pub fn clear(&mut self) {
         let _canvas_clear_span = info_span!("canvas_clear").entered();
-        if let Some(data) = self.color.data.as_mut() {
-            for old_pixel in
-                data.chunks_mut(self.color.texture_descriptor.format.pixel_size().unwrap())
-            {
-                old_pixel.copy_from_slice(&[0; 4]);
+        // Transparent black is all zeros in every format
+        self.color.fill(0);
+        self.depth.fill(self.depth_clear_value);
+        if let Some(samples) = self.samples.as_mut() {
+            samples.fill(0);
+        }
+    }
+
+    /// Encoding of the pixels of the canvas, see [`PixelFormat::new`] for the supported formats
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Sets the value the depth buffer is reset to by [`GlaciersCanvas::clear`]. It starts as
+    /// the clear value of the depth buffer, see
+    /// [`crate::depth::DepthBuffer::set_clear_value`].
+    pub fn set_depth_clear_value(&mut self, value: f32) {
+        self.depth_clear_value = value;
+    }
+
+    /// Averages the samples of every pixel into the canvas image. Does nothing when
+    /// multisampling and high dynamic range are disabled.
+    ///
+    /// High dynamic range samples are scaled by the exposure and tonemapped after being averaged,
+    /// see [`GlaciersContext::tonemapping`](crate::GlaciersContext::tonemapping).
+    ///
+    /// This is called when the canvas is dropped, so it only needs to be called manually to read
+    /// the image while still drawing.
+    pub fn resolve(&mut self) {
+        let Some(samples) = self.samples.as_deref() else {
+            return;
+        };
+        let _canvas_resolve_span = info_span!("canvas_resolve").entered();
+        let (format, sample_format) = (self.format, self.sample_format);
+        let tonemap = sample_format.is_hdr() && !format.is_hdr();
+        let (tonemapping, exposure) = (self.tonemapping, self.exposure.exp2());
+        let count = self.msaa.samples() as usize;
+        for (pixel, samples) in self
+            .color
+            .chunks_exact_mut(format.size())
+            .zip(samples.chunks_exact(count * sample_format.size()))
+        {
+            // Averaging in linear space gives the same result as a gpu resolve
+            let mut color = samples
+                .chunks_exact(sample_format.size())
+                .map(|sample| sample_format.decode(sample))
+                .sum::<Vec4>()
+                / count as f32;
+            if tonemap {
+                color = tonemapping
+                    .apply(color.xyz() * exposure)
+                    .extend(color.w.clamp(0.0, 1.0));
+            }
+            format.encode(color, pixel);
+        }
+    }
+
+    /// Mask with a bit set for every sample of a pixel
+    #[inline(always)]
+    pub(crate) fn all_samples(&self) -> u32 {
+        (1 << self.msaa.samples()) - 1
+    }
+
+    /// Tests the depth of the samples in `coverage` against the depth buffer and returns the
+    /// ones that passed.
+    ///
+    /// The depth of each sample is extrapolated from the `depth` at the pixel center using the
+    /// screen space depth `gradient`. Fragments outside the canvas always fail.
+    #[inline(always)]
+    pub(crate) fn depth_test(
+        &self,
+        pos: IVec2,
+        coverage: u32,
+        depth: f32,
+        gradient: Vec2,
+        state: &DepthState,
+    ) -> u32 {
+        let Some(index) = self.pixel_index(pos) else {
+            return 0;
+        };
+        let index = index * self.msaa.samples() as usize;
+        let mut passed = 0;
+        for sample in 0..self.msaa.samples() as usize {
+            if coverage & (1 << sample) == 0 {
+                continue;
+            }
+            let sample_depth = depth + gradient.dot(self.sample_offsets[sample]);
+            if state.test(sample_depth, self.depth[index + sample]) {
+                passed |= 1 << sample;
+            }
+        }
+        passed
+    }
+
+    /// Stores the depth of the samples that passed [`GlaciersCanvas::depth_test`] if depth
+    /// writes are enabled.
+    #[inline(always)]
+    pub(crate) fn write_depth(
+        &mut self,
+        pos: IVec2,
+        coverage: u32,
+        depth: f32,
+        gradient: Vec2,
+        state: &DepthState,
+    ) {
+        if !state.write_enabled {
+            return;
+        }
+        let Some(index) = self.pixel_index(pos) else {
+            return;
+        };
+        let index = index * self.msaa.samples() as usize;
+        for sample in 0..self.msaa.samples() as usize {
+            if coverage & (1 << sample) != 0 {
+                self.depth[index + sample] = depth + gradient.dot(self.sample_offsets[sample]);
+            }
+        }
+    }
+
+    /// Runs the fragment stage for the 2x2 quad of pixels starting at `quad`: depth test,
+    /// varyings interpolation, shading and output.
+    ///
+    /// The varyings of the pixels of the quad that aren't covered are still interpolated so the
+    /// derivatives are available. With multisampling, coverage and depth are per sample but the
+    /// shader still runs once per pixel.
+    ///
+    /// `quad_edges` are the edge functions at the center of the first pixel of the quad. Returns
+    /// false if no pixel of the quad is covered.
+    #[inline(always)]
+    fn shade_quad(
+        &mut self,
+        quad: IVec2,
+        quad_edges: I64Vec3,
+        edges: &FixedPointEdges,
+        attributes: &TriangleAttributes<f32>,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) -> bool {
+        let pixels = [quad, quad + IVec2::X, quad + IVec2::Y, quad + IVec2::ONE];
+        let edge_values = [
+            quad_edges,
+            quad_edges + edges.step_x,
+            quad_edges + edges.step_y,
+            quad_edges + edges.step_x + edges.step_y,
+        ];
+        let coverage = [0, 1, 2, 3].map(|i| {
+            if self.contains(pixels[i]) {
+                edges.coverage(edge_values[i])
+            } else {
+                0
+            }
+        });
+        if coverage == [0; 4] {
+            return false;
+        }
+
+        let weights = edge_values.map(|e| edges.barycentric(e).to_array());
+        let varyings = weights.map(|w| attributes.varyings(w));
+        let ddx = varyings[1] - varyings[0];
+        let ddy = varyings[2] - varyings[0];
+        let depths = weights.map(|w| attributes.depth(w));
+        let depth_gradient = Vec2::new(depths[1] - depths[0], depths[2] - depths[0]);
+        let depth_slope = depth_gradient.abs().max_element();
+
+        for i in 0..4 {
+            if coverage[i] == 0 {
+                continue;
+            }
+            let pos = pixels[i];
+            let depth = state.depth.biased(depths[i], depth_slope);
+            let mask = self.depth_test(pos, coverage[i], depth, depth_gradient, &state.depth);
+            if mask == 0 {
+                continue;
+            }
+            let fragment = Fragment {
+                pos: pos.as_uvec2(),
+                depth,
+                varyings: varyings[i],
+                ddx,
+                ddy,
+            };
+            if let Some(color) = shader.shade(&fragment) {
+                self.write_depth(pos, mask, depth, depth_gradient, &state.depth);
+                self.write_color(pos, mask, color, state);
+            }
+        }
+        true
+    }
+
+    /// Wide version of [`GlaciersCanvas::shade_quad`] for 2 quads laid out like
+    /// [`FragmentX8`]
+    #[inline(always)]
+    fn shade_quads_x8(
+        &mut self,
+        pos: Vec2x8,
+        weights: Vec3x8,
+        coverage: [u32; 8],
+        attributes: &TriangleAttributes<f32x8>,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        let weights = [weights.x, weights.y, weights.z];
+
+        let ps: [IVec2; 8] = <[Vec2; 8]>::from(pos).map(|p| p.as_ivec2());
+        let mut depths = attributes.depth(weights).to_array();
+        let depth_gradients =
+            [0, 2].map(|i| Vec2::new(depths[i + 1] - depths[i], depths[i + 4] - depths[i]));
+        let depth_gradient = |lane: usize| depth_gradients[(lane % 4) / 2];
+        for (i, depth) in depths.iter_mut().enumerate() {
+            *depth = state
+                .depth
+                .biased(*depth, depth_gradient(i).abs().max_element());
+        }
+
+        let mut coverage = coverage;
+        for (i, lane) in coverage.iter_mut().enumerate() {
+            if !self.contains(ps[i]) {
+                *lane = 0;
+            } else if *lane != 0 {
+                *lane = self.depth_test(ps[i], *lane, depths[i], depth_gradient(i), &state.depth);
+            }
+        }
+        if coverage == [0; 8] {
+            return;
+        }
+
+        let varyings = attributes.varyings(weights);
+        let (ddx, ddy) = varyings.quad_derivatives();
+        let mut fragment = FragmentX8 {
+            pos,
+            depth: f32x8::new(depths),
+            varyings,
+            ddx,
+            ddy,
+            mask: coverage.map(|c| c != 0),
+        };
+        let color = shader.shade_x8(&mut fragment);
+
+        // Unwiden stuff and draw the points
+        let rgb: [Vec3; 8] = color.rgb.into();
+        let alpha = color.alpha.to_array();
+        for i in 0..8 {
+            if fragment.mask[i] {
+                let gradient = depth_gradient(i);
+                self.write_depth(ps[i], coverage[i], depths[i], gradient, &state.depth);
+                self.write_color(ps[i], coverage[i], rgb[i].extend(alpha[i]), state);
             }
         }
     }
 
-    pub fn draw_point(&mut self, pos: UVec2, color: [u8; 4]) {
-        let width = self.color.texture_descriptor.size.width;
-        let pixel_offset = pos.y * width + pos.x;
-        let offset = pixel_offset as usize * self.pixel_size;
+    /// Writes a color to every sample of a pixel using the blend state of `state`. Depth is
+    /// ignored and points outside of the canvas or the scissor rectangle are dropped.
+    pub fn draw_point(&mut self, pos: IVec2, color: Color, state: &DrawState) {
+        self.begin_primitive(Primitive::Point(pos));
+        self.write_color(pos, self.all_samples(), color.to_linear().to_vec4(), state);
+    }
+
+    /// Blends a linear color with the samples in `coverage` if needed and stores it
+    #[inline(always)]
+    pub(crate) fn write_color(
+        &mut self,
+        pos: IVec2,
+        coverage: u32,
+        color: Vec4,
+        state: &DrawState,
+    ) {
+        let Some(blend_state) = &state.blend else {
+            self.store_samples(pos, coverage, color);
+            return;
+        };
+        let Some(index) = self.pixel_index(pos) else {
+            return;
+        };
+        match self.samples.as_mut() {
+            Some(samples) => {
+                let format = self.sample_format;
+                let len = self.msaa.samples() as usize * format.size();
+                let pixel = &mut samples[index * len..(index + 1) * len];
+                for (i, sample) in pixel.chunks_exact_mut(format.size()).enumerate() {
+                    if coverage & (1 << i) != 0 {
+                        let dst = format.decode(sample);
+                        let color = blend(blend_state, color, dst, state.blend_constant);
+                        format.encode(color, sample);
+                    }
+                }
+            }
+            None => {
+                let format = self.format;
+                let pixel = &mut self.color[index * format.size()..(index + 1) * format.size()];
+                let color = blend(
+                    blend_state,
+                    color,
+                    format.decode(pixel),
+                    state.blend_constant,
+                );
+                format.encode(color, pixel);
+            }
+        }
+    }
 
-        let data = self.color.data.as_mut().unwrap();
-        if offset + 3 > data.len() {
+    /// Stores a color in the samples in `coverage`, or directly in the canvas image when there
+    /// is no sample buffer
+    #[inline(always)]
+    fn store_samples(&mut self, pos: IVec2, coverage: u32, color: Vec4) {
+        let Some(index) = self.pixel_index(pos) else {
             return;
+        };
+        match self.samples.as_mut() {
+            Some(samples) => {
+                let size = self.sample_format.size();
+                let len = self.msaa.samples() as usize * size;
+                // Encoded once and copied to every covered sample
+                let mut encoded = [0; MAX_PIXEL_SIZE];
+                self.sample_format.encode(color, &mut encoded);
+                let pixel = &mut samples[index * len..(index + 1) * len];
+                for (i, sample) in pixel.chunks_exact_mut(size).enumerate() {
+                    if coverage & (1 << i) != 0 {
+                        sample.copy_from_slice(&encoded[..size]);
+                    }
+                }
+            }
+            None => {
+                let size = self.format.size();
+                let pixel = &mut self.color[index * size..(index + 1) * size];
+                self.format.encode(color, pixel);
+            }
         }
+    }
+
+    /// Index of a pixel in the buffers, counted in pixels. `None` if the pixel is outside of the
+    /// scissor rectangle, which is always inside of the rows of the buffers, see
+    /// [`GlaciersCanvas::check_bounds`].
+    #[inline(always)]
+    fn pixel_index(&self, pos: IVec2) -> Option<usize> {
+        self.check_bounds(pos)
+            .then(|| ((pos.y as u32 - self.rows.start) * self.size.x + pos.x as u32) as usize)
+    }
 
-        let [r, g, b, a] = color;
-        data[offset + 0] = r;
-        data[offset + 1] = g;
-        data[offset + 2] = b;
-        data[offset + 3] = a;
+    pub fn draw_line(&mut self, start: Vec3, end: Vec3, color: Color, state: &DrawState) {
+        self.draw_line_gradient(start, end, [color; 2], state);
     }
 
-    pub fn draw_line(&mut self, start: Vec3, end: Vec3, color: [u8; 4]) {
+    /// Draws a line whose color is interpolated from `colors[0]` at `start` to `colors[1]` at
+    /// `end`, in linear space.
+    pub fn draw_line_gradient(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        colors: [Color; 2],
+        state: &DrawState,
+    ) {
+        self.rasterize_line(start, end, colors, 0.0, state);
+    }
+
+    /// Bresenham line with the depth and color interpolated along the major axis. The line is
+    /// clipped to the canvas first so only visible pixels are visited.
+    ///
+    /// `depth_slope` is the slope used by the depth bias, lines drawn on their own don't have
+    /// one but wireframes use the slope of their triangle.
+    fn rasterize_line(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        colors: [Color; 2],
+        depth_slope: f32,
+        state: &DrawState,
+    ) {
+        self.begin_primitive(Primitive::Line(start, end));
+        let Some((t0, t1)) = clip_line(start.xy(), end.xy(), self.bounds()) else {
+            return;
+        };
+        let [c0, c1] = colors.map(|color| color.to_linear().to_vec4());
+        let colors = [c0.lerp(c1, t0), c0.lerp(c1, t1)];
+        let (start, end) = (start.lerp(end, t0), start.lerp(end, t1));
+
         let mut x0 = start.x as i32;
         let mut y0 = start.y as i32;
         let x1 = end.x as i32;
@@ -56,12 +623,28 @@ impl<'a> GlaciersCanvas<'a> {
         let sy = if y0 < y1 { 1 } else { -1 };
         let mut err = dx + dy;
 
+        let steps = dx.max(-dy).max(1) as f32;
+        let mut step = 0;
+
         loop {
-            self.draw_point(UVec2::new(x0 as u32, y0 as u32), color);
+            let pos = IVec2::new(x0, y0);
+            let t = step as f32 / steps;
+            let depth = state.depth.biased(start.z.lerp(end.z, t), depth_slope);
+            // Endpoints clipped exactly on the right or bottom edge are just outside
+            let coverage = if self.contains(pos) {
+                self.depth_test(pos, self.all_samples(), depth, Vec2::ZERO, &state.depth)
+            } else {
+                0
+            };
+            if coverage != 0 {
+                self.write_depth(pos, coverage, depth, Vec2::ZERO, &state.depth);
+                self.write_color(pos, coverage, colors[0].lerp(colors[1], t), state);
+            }
 
             if x0 == x1 && y0 == y1 {
                 break;
             }
+            step += 1;
             let e2 = 2 * err;
             if e2 >= dy {
                 err += dy;
@@ -74,144 +657,144 @@ impl<'a> GlaciersCanvas<'a> {
         }
     }
 
+    /// Draws the edges of a triangle. The depth bias of `state` uses the slope of the triangle so
+    /// the edges can be drawn on top of the filled triangle.
     pub fn draw_triangle_wireframe(
         &mut self,
-        Triangle { vertices, .. }: &Triangle,
-        color: [u8; 4],
+        triangle: &Triangle,
+        color: Color,
+        state: &DrawState,
     ) {
-        self.draw_line(vertices[0].pos, vertices[1].pos, color);
-        self.draw_line(vertices[1].pos, vertices[2].pos, color);
-        self.draw_line(vertices[2].pos, vertices[0].pos, color);
+        let slope = triangle.depth_slope();
+        let [a, b, c] = triangle.vertices.map(|v| v.pos);
+        self.rasterize_line(a, b, [color; 2], slope, state);
+        self.rasterize_line(b, c, [color; 2], slope, state);
+        self.rasterize_line(c, a, [color; 2], slope, state);
     }
 
-    pub fn draw_triangle(&mut self, triangle: &Triangle) {
-        // returns double the signed area of the triangle
-        fn edge_function(a: IVec2, b: IVec2, c: IVec2) -> i32 {
-            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    /// Draws a triangle in canvas space with the rasterizer picked by
+    /// [`GlaciersCanvas::rasterizer`], see [`RasterizerStrategy`]
+    pub fn draw_triangle(
+        &mut self,
+        triangle: &Triangle,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        let show_outline = self.show_box_outlines;
+        match self.rasterizer.select(triangle.aabb) {
+            RasterizerStrategy::Scalar => self.draw_triangle_scalar(triangle, state, shader),
+            RasterizerStrategy::ScalarBox => {
+                self.draw_triangle_box(triangle, state, shader, show_outline)
+            }
+            // Auto is always resolved by select
+            RasterizerStrategy::Wide | RasterizerStrategy::Auto => {
+                self.draw_triangle_wide(triangle, state, shader)
+            }
+            RasterizerStrategy::WideBox => {
+                self.draw_triangle_wide_box(triangle, state, shader, show_outline)
+            }
         }
+    }
 
-        if !triangle.is_visible() {
+    pub fn draw_triangle_scalar(
+        &mut self,
+        triangle: &Triangle,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        self.begin_primitive(Primitive::Triangle(triangle.vertices.map(|v| v.pos)));
+        if !triangle.is_visible(state) {
             return;
         }
 
-        let Triangle {
-            vertices,
-            aabb: (min, max),
-        } = triangle;
-        let a = vertices[0].pos.xy().as_ivec2();
-        let b = vertices[1].pos.xy().as_ivec2();
-        let c = vertices[2].pos.xy().as_ivec2();
-        let abc = edge_function(a, b, c);
-
-        for y in min.y as i32..=max.y as i32 {
-            for x in min.x as i32..=max.x as i32 {
-                let p = IVec2::new(x, y);
-
-                let abp = edge_function(a, b, p);
-                let bcp = edge_function(b, c, p);
-                let cap = edge_function(c, a, p);
-
-                if abp <= 0 && bcp <= 0 && cap <= 0 {
-                    let weights = IVec3::new(bcp, cap, abp).as_vec3a() / abc as f32;
-                    let color =
-                        Mat3::from_cols(vertices[0].color, vertices[1].color, vertices[2].color)
-                            * weights;
-                    let color =
-                        [color.x, color.y, color.z, 1.0].map(|v| (v * u8::MAX as f32) as u8);
-
-                    self.draw_point(p.as_uvec2(), color);
-                }
+        let Some((min, max)) = self.clamp_aabb(triangle.aabb) else {
+            return;
+        };
+        let vertices = triangle.ccw_vertices();
+        let edges = FixedPointEdges::new(&vertices, self.msaa());
+        let attributes = TriangleAttributes::<f32>::new(&vertices, state.interpolation);
+        let quad_min = quad_aligned(min);
+
+        // The edge functions are only evaluated for the first quad, then stepped by additions
+        let mut row_edges = edges.evaluate(quad_min);
+        for y in (quad_min.y..=max.y as i32).step_by(2) {
+            let mut quad_edges = row_edges;
+            for x in (quad_min.x..=max.x as i32).step_by(2) {
+                let quad = IVec2::new(x, y);
+                self.shade_quad(quad, quad_edges, &edges, &attributes, state, shader);
+                quad_edges += edges.step_x * 2;
             }
+            row_edges += edges.step_y * 2;
         }
     }
 
-    pub fn draw_triangle_box(&mut self, triangle: &Triangle, show_outline: bool) {
-        // returns double the signed area of the triangle
-        fn edge_function(a: IVec2, b: IVec2, c: IVec2) -> i32 {
-            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
-        }
-
-        if !triangle.is_visible() {
+    pub fn draw_triangle_box(
+        &mut self,
+        triangle: &Triangle,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+        show_outline: bool,
+    ) {
+        self.begin_primitive(Primitive::Triangle(triangle.vertices.map(|v| v.pos)));
+        if !triangle.is_visible(state) {
             return;
         }
 
-        let Triangle {
-            vertices,
-            aabb: (min, max),
-        } = triangle;
-        let a = vertices[0].pos.xy().as_ivec2();
-        let b = vertices[1].pos.xy().as_ivec2();
-        let c = vertices[2].pos.xy().as_ivec2();
-        let abc = edge_function(a, b, c);
+        let Some((min, max)) = self.clamp_aabb(triangle.aabb) else {
+            return;
+        };
+        let vertices = triangle.ccw_vertices();
+        let edges = FixedPointEdges::new(&vertices, self.msaa());
+        let attributes = TriangleAttributes::<f32>::new(&vertices, state.interpolation);
+        let quad_min = quad_aligned(min);
 
         // I need to use a macro because the inline annotation is not aggressive enough
-        macro_rules! draw_point {
-            ($x: ident, $y: ident) => {
-                let p = IVec2::new($x, $y);
-
-                let abp = edge_function(a, b, p);
-                let bcp = edge_function(b, c, p);
-                let cap = edge_function(c, a, p);
-
-                if abp <= 0 && bcp <= 0 && cap <= 0 {
-                    let weights = IVec3::new(bcp, cap, abp).as_vec3a() / abc as f32;
-                    let color =
-                        Mat3::from_cols(vertices[0].color, vertices[1].color, vertices[2].color)
-                            * weights;
-                    let color =
-                        [color.x, color.y, color.z, 1.0].map(|v| (v * u8::MAX as f32) as u8);
-
-                    self.draw_point(p.as_uvec2(), color);
-                }
+        macro_rules! shade_quad {
+            ($x: ident, $y: ident, $edges: ident) => {
+                self.shade_quad(
+                    IVec2::new($x, $y),
+                    $edges,
+                    &edges,
+                    &attributes,
+                    state,
+                    shader,
+                )
             };
         }
 
-        // println!("--- start ---");
-
-        // TODO use the same algorithm as the wide_box
         // This should probably be relative to resolution scale
         let block_size: i32 = 8;
         let orient = (max.x - min.x) / (max.y - min.y);
-        if orient >= 0.4 && orient <= 1.6 {
-            for y in (min.y as i32..=max.y as i32).step_by(block_size as usize) {
-                let mut pass = false;
-                for x in (min.x as i32..=max.x as i32).step_by(block_size as usize) {
-                    let c00 = IVec2::new(x, y);
-                    let c01 = IVec2::new(x, y + block_size - 1);
-                    let c10 = IVec2::new(x + block_size - 1, y);
-                    let c11 = IVec2::new(x + block_size - 1, y + block_size - 1);
-
-                    let draw_corners = |canvas: &mut Self, color| {
-                        canvas.draw_line(c00.extend(0).as_vec3(), c01.extend(0).as_vec3(), color);
-                        canvas.draw_line(c01.extend(0).as_vec3(), c11.extend(0).as_vec3(), color);
-                        canvas.draw_line(c11.extend(0).as_vec3(), c10.extend(0).as_vec3(), color);
-                        canvas.draw_line(c10.extend(0).as_vec3(), c00.extend(0).as_vec3(), color);
-                    };
-
-                    let corners = [c00, c01, c10, c11].map(|p| {
-                        let abp = edge_function(a, b, p);
-                        let bcp = edge_function(b, c, p);
-                        let cap = edge_function(c, a, p);
-                        abp <= 0 && bcp <= 0 && cap <= 0
-                    });
-                    if corners.iter().any(|&c| c) {
-                        // at least one point is inside the triangle
-                        for y in c00.y as i32..=c11.y as i32 {
-                            for x in c00.x as i32..=c11.x as i32 {
-                                draw_point!(x, y);
-                            }
-                        }
-                        if show_outline {
-                            draw_corners(self, [0, 0xff, 0, 0xff]);
-                        }
-                        pass = true;
-                    } else {
-                        if show_outline {
-                            draw_corners(self, [0xff, 0, 0, 0xff]);
-                        }
-                        if pass {
-                            break;
+        let block_step_x = edges.step_x * block_size as i64;
+        let block_step_y = edges.step_y * block_size as i64;
+        if BOX_ORIENT.contains(&orient) {
+            let max = max.xy().as_ivec2();
+            let mut block_row_edges = edges.evaluate(quad_min);
+            for y in (quad_min.y..=max.y).step_by(block_size as usize) {
+                let mut next_block_edges = block_row_edges;
+                block_row_edges += block_step_y;
+                for x in (quad_min.x..=max.x).step_by(block_size as usize) {
+                    let block = IVec2::new(x, y);
+                    let block_edges = next_block_edges;
+                    next_block_edges += block_step_x;
+                    if !edges.overlaps_block(block_edges, block_size) {
+                        continue;
+                    }
+
+                    let mut has_drawn = false;
+                    let block_max = (block + block_size - 1).min(max);
+                    let mut row_edges = block_edges;
+                    for y in (block.y..=block_max.y).step_by(2) {
+                        let mut quad_edges = row_edges;
+                        for x in (block.x..=block_max.x).step_by(2) {
+                            has_drawn |= shade_quad!(x, y, quad_edges);
+                            quad_edges += edges.step_x * 2;
                         }
+                        row_edges += edges.step_y * 2;
+                    }
+                    if show_outline {
+                        let color = if has_drawn { LIME } else { RED };
+                        self.draw_block_outline(block, block_size, color.into());
                     }
                 }
             }
@@ -220,305 +803,556 @@ impl<'a> GlaciersCanvas<'a> {
                 self.draw_line(
                     Vec3::new(min.x, min.y, 0.0),
                     Vec3::new(min.x, max.y, 0.0),
-                    [0, 0xff, 0, 0xff],
+                    LIME.into(),
+                    &DrawState::OVERLAY,
                 );
                 self.draw_line(
                     Vec3::new(min.x, max.y, 0.0),
                     Vec3::new(max.x, max.y, 0.0),
-                    [0, 0xff, 0, 0xff],
+                    LIME.into(),
+                    &DrawState::OVERLAY,
                 );
                 self.draw_line(
                     Vec3::new(max.x, max.y, 0.0),
                     Vec3::new(max.x, min.y, 0.0),
-                    [0, 0xff, 0, 0xff],
+                    LIME.into(),
+                    &DrawState::OVERLAY,
                 );
                 self.draw_line(
                     Vec3::new(max.x, min.y, 0.0),
                     Vec3::new(min.x, min.y, 0.0),
-                    [0, 0xff, 0, 0xff],
+                    LIME.into(),
+                    &DrawState::OVERLAY,
                 );
             }
 
-            for y in min.y as i32..=max.y as i32 {
-                for x in min.x as i32..=max.x as i32 {
-                    draw_point!(x, y);
+            let mut row_edges = edges.evaluate(quad_min);
+            for y in (quad_min.y..=max.y as i32).step_by(2) {
+                let mut quad_edges = row_edges;
+                for x in (quad_min.x..=max.x as i32).step_by(2) {
+                    shade_quad!(x, y, quad_edges);
+                    quad_edges += edges.step_x * 2;
                 }
+                row_edges += edges.step_y * 2;
             }
         }
     }
 
-    pub fn draw_triangle_wide(&mut self, triangle: &Triangle) {
-        fn edge_function_wide(a: Vec2x8, b: Vec2x8, c: Vec2x8) -> f32x8 {
-            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    pub fn draw_triangle_wide(
+        &mut self,
+        triangle: &Triangle,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        if let Some(setup) = TriangleSetup::new(self, triangle, state) {
+            self.rasterize_wide(&setup, state, shader);
         }
+    }
 
-        if !triangle.is_visible() {
+    /// Rasterizes a triangle with the wide rasterizer once its setup is done, see
+    /// [`GlaciersCanvas::draw_triangle_wide`]
+    pub(crate) fn rasterize_wide(
+        &mut self,
+        setup: &TriangleSetup,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+    ) {
+        self.begin_primitive(Primitive::Triangle(setup.vertices.map(|v| v.pos)));
+        // The setup may be for a larger scissor rectangle, see `draw_triangles_parallel`
+        let Some((min, max)) = self.clamp_aabb((setup.min, setup.max)) else {
             return;
+        };
+        let edges = WideEdges::new(&setup.edges);
+        let attributes = TriangleAttributes::<f32x8>::new(&setup.vertices, state.interpolation);
+        // Blocks start on a multiple of 4 pixels so a triangle is split in the same quads when
+        // only a tile of it is drawn, see `draw_triangles_parallel`
+        let block_min = IVec2::new(min.x as i32 & !3, min.y as i32 & !1);
+
+        // The edge functions are only evaluated for the first block, then stepped by additions
+        let mut row_edges = setup.edges.evaluate(block_min);
+        for y in (block_min.y..=max.y as i32).step_by(2) {
+            let mut block_edges = row_edges;
+            for x in (block_min.x..=max.x as i32).step_by(4) {
+                if let Some(coverage) = edges.coverage(block_edges) {
+                    let weights = edges.barycentric(block_edges);
+                    let pos = quads_x8(x, y);
+                    self.shade_quads_x8(pos, weights, coverage, &attributes, state, shader);
+                }
+                block_edges += edges.step_x;
+            }
+            row_edges += edges.step_y;
         }
+    }
 
-        let Triangle {
-            vertices,
-            aabb: (min, max),
-        } = triangle;
-        let a = vertices[0].pos.xy();
-        let b = vertices[1].pos.xy();
-        let c = vertices[2].pos.xy();
-
-        let a = Vec2x8::new_splat(a.x as f32, a.y as f32);
-        let b = Vec2x8::new_splat(b.x as f32, b.y as f32);
-        let c = Vec2x8::new_splat(c.x as f32, c.y as f32);
-        let abc = edge_function_wide(a, b, c);
-
-        let color_a = Vec3x8::splat(vertices[0].color);
-        let color_b = Vec3x8::splat(vertices[1].color);
-        let color_c = Vec3x8::splat(vertices[2].color);
-
-        const SIMD_SIZE: usize = 8;
-        for y in min.y as i32..=max.y as i32 {
-            for x in (min.x as i32..=max.x as i32).step_by(SIMD_SIZE) {
-                let mut x_wide = [0.0_f32; SIMD_SIZE];
-                for i in 0..SIMD_SIZE {
-                    x_wide[i] = x as f32 + i as f32;
-                }
-                let p_wide = Vec2x8::new(f32x8::new(x_wide), f32x8::splat(y as f32));
-
-                let abp = edge_function_wide(a, b, p_wide);
-                let bcp = edge_function_wide(b, c, p_wide);
-                let cap = edge_function_wide(c, a, p_wide);
-
-                let weights = Vec3x8::new(bcp, cap, abp) / abc;
-                let r = color_a.x * weights.x + color_b.x * weights.y + color_c.x * weights.z;
-                let g = color_a.y * weights.x + color_b.y * weights.y + color_c.y * weights.z;
-                let b = color_a.z * weights.x + color_b.z * weights.y + color_c.z * weights.z;
-
-                // Assumes winding order is CCW
-                // TODO need to make winding order configurable
-                let abp_cmp = boolf32x8::from(abp.cmp_le(0.0));
-                let bcp_cmp = boolf32x8::from(bcp.cmp_le(0.0));
-                let cap_cmp = boolf32x8::from(cap.cmp_le(0.0));
-                let check = abp_cmp & bcp_cmp & cap_cmp;
-
-                if !check.any() {
-                    // All lanes are false which means there's nothing to draw
+    pub fn draw_triangle_wide_box(
+        &mut self,
+        triangle: &Triangle,
+        state: &DrawState,
+        shader: &impl FragmentShader,
+        show_outline: bool,
+    ) {
+        const BLOCK_SIZE: i32 = 8;
+
+        self.begin_primitive(Primitive::Triangle(triangle.vertices.map(|v| v.pos)));
+        if !triangle.is_visible(state) {
+            return;
+        };
+        let Some((min, max)) = self.clamp_aabb(triangle.aabb) else {
+            return;
+        };
+        let vertices = triangle.ccw_vertices();
+        let fixed_point_edges = FixedPointEdges::new(&vertices, self.msaa());
+        let edges = WideEdges::new(&fixed_point_edges);
+        let attributes = TriangleAttributes::<f32x8>::new(&vertices, state.interpolation);
+        let block_step_x = fixed_point_edges.step_x * BLOCK_SIZE as i64;
+        let block_step_y = fixed_point_edges.step_y * BLOCK_SIZE as i64;
+
+        // Blocks start on a multiple of 4 pixels like in `rasterize_wide`, so the triangle is
+        // split in the same quads
+        let block_min = IVec2::new(min.x as i32 & !3, min.y as i32 & !1);
+        let max = max.xy().as_ivec2();
+        let mut block_row_edges = fixed_point_edges.evaluate(block_min);
+        for block_y in (block_min.y..=max.y).step_by(BLOCK_SIZE as usize) {
+            let mut next_block_edges = block_row_edges;
+            block_row_edges += block_step_y;
+            for block_x in (block_min.x..=max.x).step_by(BLOCK_SIZE as usize) {
+                let block = IVec2::new(block_x, block_y);
+                let block_edges = next_block_edges;
+                next_block_edges += block_step_x;
+                if !fixed_point_edges.overlaps_block(block_edges, BLOCK_SIZE) {
                     continue;
                 }
 
-                // Unwiden stuff and draw the points
-                let color: [Vec3; SIMD_SIZE] = Vec3x8::new(r, g, b).into();
-                let ps: [Vec2; SIMD_SIZE] = p_wide.into();
-                let check = check.to_array();
-
-                for i in 0..SIMD_SIZE {
-                    if check[i] {
-                        self.draw_point(
-                            ps[i].as_uvec2(),
-                            [color[i].x, color[i].y, color[i].z, 1.0]
-                                .map(|v| (v * u8::MAX as f32) as u8),
-                        );
+                let mut has_drawn = false;
+                let block_max = (block + BLOCK_SIZE - 1).min(max);
+                let mut row_edges = block_edges;
+                for y in (block.y..=block_max.y).step_by(2) {
+                    let mut lane_edges = row_edges;
+                    for x in (block.x..=block_max.x).step_by(4) {
+                        if let Some(coverage) = edges.coverage(lane_edges) {
+                            has_drawn = true;
+                            let weights = edges.barycentric(lane_edges);
+                            let pos = quads_x8(x, y);
+                            self.shade_quads_x8(pos, weights, coverage, &attributes, state, shader);
+                        }
+                        lane_edges += edges.step_x;
                     }
+                    row_edges += edges.step_y;
+                }
+                if show_outline {
+                    let color = if has_drawn { LIME } else { RED };
+                    self.draw_block_outline(block, BLOCK_SIZE, color.into());
                 }
             }
         }
     }
 
-    pub fn draw_triangle_wide_box(&mut self, triangle: &Triangle, show_outline: bool) {
-        const SIMD_SIZE: usize = 8;
-        const BLOCK_SIZE: i32 = SIMD_SIZE as i32;
+    /// Outlines a square block of `size` pixels starting at `min`, used by the box rasterizers
+    /// to show the blocks they visited
+    fn draw_block_outline(&mut self, min: IVec2, size: i32, color: Color) {
+        let max = min + size - 1;
+        let corners = [min, IVec2::new(min.x, max.y), max, IVec2::new(max.x, min.y)]
+            .map(|corner| corner.extend(0).as_vec3());
+        for i in 0..4 {
+            self.draw_line(corners[i], corners[(i + 1) % 4], color, &DrawState::OVERLAY);
+        }
+    }
+}
 
-        fn edge_function_wide(a: Vec2x8, b: Vec2x8, c: Vec2x8) -> f32x8 {
-            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+impl Drop for GlaciersCanvas<'_> {
+    fn drop(&mut self) {
+        if self.resolve_on_drop {
+            self.resolve();
         }
+    }
+}
 
-        if !triangle.is_visible() {
-            return;
-        };
+/// Rounds the top left corner of a bounding box down to the first pixel of a 2x2 quad, so quads
+/// are always aligned on even coordinates.
+#[inline(always)]
+fn quad_aligned(min: Vec3) -> IVec2 {
+    IVec2::new(min.x as i32 & !1, min.y as i32 & !1)
+}
 
-        let Triangle {
-            vertices,
-            aabb: (min, max),
-        } = triangle;
-        let a = vertices[0].pos.xy();
-        let b = vertices[1].pos.xy();
-        let c = vertices[2].pos.xy();
-
-        let a = Vec2x8::new_splat(a.x as f32, a.y as f32);
-        let b = Vec2x8::new_splat(b.x as f32, b.y as f32);
-        let c = Vec2x8::new_splat(c.x as f32, c.y as f32);
-        let abc = edge_function_wide(a, b, c);
-
-        let color_a = Vec3x8::splat(vertices[0].color);
-        let color_b = Vec3x8::splat(vertices[1].color);
-        let color_c = Vec3x8::splat(vertices[2].color);
-
-        let draw_block = |canvas: &mut Self, x, y| {
-            let mut has_drawn = false;
-            let c00 = IVec2::new(x, y);
-            let c01 = IVec2::new(x, y + BLOCK_SIZE - 1);
-            let c10 = IVec2::new(x + BLOCK_SIZE - 1, y);
-            let c11 = IVec2::new(x + BLOCK_SIZE - 1, y + BLOCK_SIZE - 1);
-
-            let draw_corners = |canvas: &mut Self, color| {
-                canvas.draw_line(c00.extend(0).as_vec3(), c01.extend(0).as_vec3(), color);
-                canvas.draw_line(c01.extend(0).as_vec3(), c11.extend(0).as_vec3(), color);
-                canvas.draw_line(c11.extend(0).as_vec3(), c10.extend(0).as_vec3(), color);
-                canvas.draw_line(c10.extend(0).as_vec3(), c00.extend(0).as_vec3(), color);
-            };
+/// Double the signed area of the triangles `abc` of each lane, see [`Triangle::signed_area`]
+#[inline(always)]
+fn edge_function_wide(a: Vec2x8, b: Vec2x8, c: Vec2x8) -> f32x8 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
 
-            for y in c00.y as i32..=c11.y as i32 {
-                let mut x_wide = [0.0_f32; SIMD_SIZE];
-                for i in 0..SIMD_SIZE {
-                    x_wide[i] = x as f32 + i as f32;
-                }
-                let p_wide = Vec2x8::new(f32x8::new(x_wide), f32x8::splat(y as f32));
-
-                let abp = edge_function_wide(a, b, p_wide);
-                let bcp = edge_function_wide(b, c, p_wide);
-                let cap = edge_function_wide(c, a, p_wide);
-
-                let weights = Vec3x8::new(bcp, cap, abp) / abc;
-                let r = color_a.x * weights.x + color_b.x * weights.y + color_c.x * weights.z;
-                let g = color_a.y * weights.x + color_b.y * weights.y + color_c.y * weights.z;
-                let b = color_a.z * weights.x + color_b.z * weights.y + color_c.z * weights.z;
-
-                // Assumes winding order is CCW
-                // TODO need to make winding order configurable
-                let abp_cmp = boolf32x8::from(abp.cmp_le(0.0));
-                let bcp_cmp = boolf32x8::from(bcp.cmp_le(0.0));
-                let cap_cmp = boolf32x8::from(cap.cmp_le(0.0));
-                let check = abp_cmp & bcp_cmp & cap_cmp;
-
-                if !check.any() {
-                    // All lanes are false which means there's nothing to draw
-                    continue;
-                }
+/// Edge functions of the lanes of a [`FragmentX8`], see [`FixedPointEdges`].
+///
+/// Only the edge functions of the first lane are stepped from block to block, the other lanes are
+/// always the same offset away from it and are tested against it all at once.
+struct WideEdges<'e> {
+    edges: &'e FixedPointEdges,
+    /// Offsets from the edge functions of the first lane to the ones of each lane, for each edge.
+    /// They are multiples of the sub pixel scale, divided by it they are exact in `f32` for
+    /// vertices within [`MAX_COORDINATE`].
+    lanes: [f32x8; 3],
+    /// Increments of the edge functions when moving 4 pixels to the right
+    step_x: I64Vec3,
+    /// Increments of the edge functions when moving 2 pixels down
+    step_y: I64Vec3,
+}
 
-                // Unwiden stuff and draw the points
-                let color: [Vec3; SIMD_SIZE] = Vec3x8::new(r, g, b).into();
-                let ps: [Vec2; SIMD_SIZE] = p_wide.into();
-                let check = check.to_array();
-
-                for i in 0..SIMD_SIZE {
-                    if check[i] {
-                        has_drawn = true;
-                        canvas.draw_point(
-                            ps[i].as_uvec2(),
-                            [color[i].x, color[i].y, color[i].z, 1.0]
-                                .map(|v| (v * u8::MAX as f32) as u8),
-                        );
-                    }
-                }
-            }
-            if show_outline {
-                if has_drawn {
-                    draw_corners(canvas, [0, 0xff, 0, 0xff]);
-                } else {
-                    draw_corners(canvas, [0xff, 0, 0, 0xff]);
-                }
+impl<'e> WideEdges<'e> {
+    fn new(edges: &'e FixedPointEdges) -> Self {
+        let offsets: [I64Vec3; 8] = std::array::from_fn(|i| {
+            (edges.step_x * (i % 4) as i64 + edges.step_y * (i / 4) as i64) >> SUBPIXEL_BITS
+        });
+        Self {
+            edges,
+            lanes: [0, 1, 2].map(|e| f32x8::new(offsets.map(|offset| offset[e] as f32))),
+            step_x: edges.step_x * 4,
+            step_y: edges.step_y * 2,
+        }
+    }
+
+    /// Returns the mask of covered samples of each lane from the edge functions of the first
+    /// lane, `None` if no sample is covered
+    #[inline(always)]
+    fn coverage(&self, edges: I64Vec3) -> Option<[u32; 8]> {
+        // The offsets are at most 2^23 so the limits are clamped to a range where they stay exact
+        const LIMIT: I64Vec3 = I64Vec3::splat(1 << 24);
+        let mut coverage = [0; 8];
+        for (sample, delta) in self.edges.sample_deltas[..self.edges.sample_count]
+            .iter()
+            .enumerate()
+        {
+            // A lane is inside when `edges + offset * scale + delta + bias <= 0` for every edge.
+            // The offsets are integers so they are compared to the other side divided by the
+            // scale and rounded down.
+            let limits = (-(edges + *delta + self.edges.bias) >> SUBPIXEL_BITS)
+                .clamp(-LIMIT, LIMIT)
+                .as_vec3();
+            let inside = boolf32x8::from(self.lanes[0].cmp_le(limits.x))
+                & boolf32x8::from(self.lanes[1].cmp_le(limits.y))
+                & boolf32x8::from(self.lanes[2].cmp_le(limits.z));
+            for (lane, inside) in coverage.iter_mut().zip(inside.to_array()) {
+                *lane |= (inside as u32) << sample;
             }
-            has_drawn
+        }
+        (coverage != [0; 8]).then_some(coverage)
+    }
+
+    /// Returns the barycentric weights of the pixel centers of the lanes, see
+    /// [`FixedPointEdges::barycentric`]
+    #[inline(always)]
+    fn barycentric(&self, edges: I64Vec3) -> Vec3x8 {
+        let lane = |e: usize| self.lanes[e] * SUBPIXEL_SCALE + edges[e] as f32;
+        Vec3x8::new(lane(0), lane(1), lane(2)) / f32x8::splat(self.edges.abc)
+    }
+}
+
+/// Pixel coordinates of the lanes of a [`FragmentX8`] starting at `(x, y)`, 2 rows of 4 pixels
+#[inline(always)]
+fn quads_x8(x: i32, y: i32) -> Vec2x8 {
+    const OFFSETS_X: [f32; 8] = [0.0, 1.0, 2.0, 3.0, 0.0, 1.0, 2.0, 3.0];
+    const OFFSETS_Y: [f32; 8] = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+    Vec2x8::new(
+        f32x8::new(OFFSETS_X.map(|o| x as f32 + o)),
+        f32x8::new(OFFSETS_Y.map(|o| y as f32 + o)),
+    )
+}
+
+/// Culling and edge setup of a triangle, ready to be rasterized by
+/// [`GlaciersCanvas::rasterize_wide`]
+pub(crate) struct TriangleSetup {
+    /// Vertices in counter clockwise order, see [`Triangle::ccw_vertices`]
+    vertices: [Vertex; 3],
+    edges: FixedPointEdges,
+    /// Bounding box clamped to the scissor rectangle, see [`GlaciersCanvas::clamp_aabb`]
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+}
+
+impl TriangleSetup {
+    /// Returns `None` if the triangle is culled or outside of the scissor rectangle
+    pub(crate) fn new(
+        canvas: &GlaciersCanvas,
+        triangle: &Triangle,
+        state: &DrawState,
+    ) -> Option<Self> {
+        if !triangle.is_visible(state) {
+            return None;
+        }
+        let (min, max) = canvas.clamp_aabb(triangle.aabb)?;
+        let vertices = triangle.ccw_vertices();
+        Some(Self {
+            edges: FixedPointEdges::new(&vertices, canvas.msaa),
+            vertices,
+            min,
+            max,
+        })
+    }
+
+    /// Does the setup of up to 8 triangles at once, one per SIMD lane. Lanes without a triangle
+    /// are `None`, like the ones culled or outside of the scissor rectangle.
+    pub(crate) fn new_x8(
+        canvas: &GlaciersCanvas,
+        triangles: &[Triangle],
+        state: &DrawState,
+    ) -> [Option<Self>; 8] {
+        debug_assert!(!triangles.is_empty() && triangles.len() <= 8);
+        // Missing lanes repeat the first triangle and are dropped at the end
+        let lane = |i: usize| triangles.get(i).unwrap_or(&triangles[0]);
+        let positions = |vertices: [[Vec3; 3]; 8]| {
+            [0, 1, 2].map(|v| {
+                Vec2x8::new(
+                    f32x8::new(vertices.map(|p| p[v].x)),
+                    f32x8::new(vertices.map(|p| p[v].y)),
+                )
+            })
         };
 
-        let mut top_start_x = min.x;
-        let mut bottom_start_x = min.x;
-        for v in triangle.vertices {
-            if v.pos.y == min.y {
-                top_start_x = v.pos.x;
-            }
-            if v.pos.y == max.y {
-                bottom_start_x = v.pos.x;
+        // Culling, with the same result as `Triangle::is_visible`
+        let [a, b, c] = positions(std::array::from_fn(|i| lane(i).vertices.map(|v| v.pos)));
+        let area = edge_function_wide(a, b, c);
+        // The canvas is y down so counter clockwise triangles have a negative area
+        let ccw = boolf32x8::from(area.cmp_lt(0.0));
+        let cw = boolf32x8::from(area.cmp_gt(0.0));
+        let (front, back) = match state.front_face {
+            FrontFace::Ccw => (ccw, cw),
+            FrontFace::Cw => (cw, ccw),
+        };
+        let visible = match state.cull_mode {
+            None => front | back,
+            Some(Face::Back) => front,
+            Some(Face::Front) => back,
+        }
+        .to_array();
+        let clamped: [_; 8] = std::array::from_fn(|i| {
+            if visible[i] && i < triangles.len() {
+                canvas.clamp_aabb(lane(i).aabb)
+            } else {
+                None
             }
+        });
+        if clamped.iter().all(Option::is_none) {
+            return [const { None }; 8];
         }
 
-        let mut y = min.y as i32;
-        let mut x = top_start_x as i32;
-        let half_y = (min.y as i32 + ((max.y as i32 - min.y as i32) >> 1));
-        loop {
-            let mut min_x = min.x as i32;
-            let mut max_x = max.x as i32;
-            // WARN this isn't inclusive so I have to handroll it :(
-            // for x in (min_x..x).rev().step_by(BLOCK_SIZE as usize) {
-            let mut x_loop = x - BLOCK_SIZE;
-            loop {
-                if !draw_block(self, x_loop, y) {
-                    min_x = x_loop + BLOCK_SIZE;
-                    break;
-                }
-                if x_loop < min.x as i32 {
-                    break;
-                }
-                x_loop -= BLOCK_SIZE;
-            }
-            for x in (x..=max.x as i32 + BLOCK_SIZE).step_by(BLOCK_SIZE as usize) {
-                if !draw_block(self, x, y) {
-                    max_x = x;
-                    break;
-                }
-            }
+        // Edge setup in counter clockwise order, see `Triangle::ccw_vertices`, with the same
+        // result as `FixedPointEdges::new`
+        let cw = cw.to_array();
+        let vertices: [_; 8] = std::array::from_fn(|i| {
+            let [a, b, c] = lane(i).vertices;
+            if cw[i] { [a, c, b] } else { [a, b, c] }
+        });
+        let [a, b, c] = positions(vertices.map(|v| v.map(|v| v.pos))).map(|p| {
+            Vec2x8::new(
+                (p.x * SUBPIXEL_SCALE).round(),
+                (p.y * SUBPIXEL_SCALE).round(),
+            )
+        });
+        let abc = edge_function_wide(a, b, c).to_array();
+        let edge = |start: Vec2x8, end: Vec2x8| {
+            let (dx, dy) = (end.x - start.x, end.y - start.y);
+            let is_top_left = boolf32x8::from(dy.cmp_gt(0.0))
+                | (boolf32x8::from(dy.cmp_eq(0.0)) & boolf32x8::from(dx.cmp_lt(0.0)));
+            let step_x = (-dy * SUBPIXEL_SCALE).to_array();
+            let step_y = (dx * SUBPIXEL_SCALE).to_array();
+            (step_x, step_y, is_top_left.to_array())
+        };
+        let edges = [edge(b, c), edge(c, a), edge(a, b)];
+        let [a, b, c] = [a, b, c].map(<[Vec2; 8]>::from);
+
+        std::array::from_fn(|i| {
+            let (min, max) = clamped[i]?;
+            let edges = FixedPointEdges::from_snapped(
+                [a[i], b[i], c[i]],
+                abc[i],
+                Vec3::from_array(edges.map(|e| e.0[i])),
+                Vec3::from_array(edges.map(|e| e.1[i])),
+                BVec3::from_array(edges.map(|e| e.2[i])),
+                canvas.msaa,
+            );
+            Some(Self {
+                edges,
+                vertices: vertices[i],
+                min,
+                max,
+            })
+        })
+    }
+}
 
-            // let min_start = Vec3::new(min_x as f32, y as f32, 0.0);
-            // let max_start = Vec3::new(max_x as f32, y as f32, 0.0);
-            // let end_offset = Vec3::new(0.0, BLOCK_SIZE as f32, 0.0);
-            // self.draw_line(min_start, min_start + end_offset, [0xff, 0xff, 0, 0]);
-            // self.draw_line(max_start, max_start + end_offset, [0xff, 0, 0xff, 0]);
+/// Number of fractional bits used to snap vertices to the sub pixel grid in the rasterizers.
+pub const SUBPIXEL_BITS: u32 = 8;
+
+/// Largest distance in pixels from the origin of the canvas to the vertices of the triangles the
+/// rasterizers draw, on each axis. Triangles beyond it are dropped, clip them first like
+/// [`GlaciersCanvas::draw_mesh`] does with its guard band.
+///
+/// Within it the edge functions fit in `i64` and the offsets between the lanes of the wide
+/// rasterizers are exact in `f32`.
+pub const MAX_COORDINATE: f32 = 4096.0;
+
+const SUBPIXEL_SCALE: f32 = (1 << SUBPIXEL_BITS) as f32;
+
+/// Edge equations of a counter clockwise triangle snapped to the sub pixel grid.
+///
+/// Pixels are sampled at their center and the top-left fill rule decides who owns pixels that
+/// land exactly on an edge, so pixels on an edge shared by two triangles are drawn exactly once.
+struct FixedPointEdges {
+    a: I64Vec2,
+    b: I64Vec2,
+    c: I64Vec2,
+    /// Double the signed area of the snapped triangle, only used to normalize the barycentric
+    /// weights
+    abc: f32,
+    /// Added to the edge functions, in the same order as [`FixedPointEdges::evaluate`], so that
+    /// pixels on edges that aren't top or left edges are excluded
+    bias: I64Vec3,
+    /// Increments of the edge functions when moving one pixel to the right
+    step_x: I64Vec3,
+    /// Increments of the edge functions when moving one pixel down
+    step_y: I64Vec3,
+    /// Offsets from the edge functions at the pixel center to the ones at each sample, only the
+    /// first `sample_count` are set
+    sample_deltas: [I64Vec3; MAX_SAMPLES],
+    sample_count: usize,
+}
 
-            y += BLOCK_SIZE;
-            if y >= half_y {
-                break;
-            }
+impl FixedPointEdges {
+    fn new(vertices: &[Vertex; 3], msaa: Msaa) -> Self {
+        // Ties are rounded to even like `f32x8::round` in `TriangleSetup::new_x8`
+        let [a, b, c] = vertices.map(|v| (v.pos.xy() * SUBPIXEL_SCALE).map(f32::round_ties_even));
+        // In canvas space, ccw triangles have the interior on the negative side of each edge.
+        // Left edges go down and top edges are horizontal and go to the left.
+        let edge = |start: Vec2, end: Vec2| {
+            let d = end - start;
+            let is_top_left = d.y > 0.0 || (d.y == 0.0 && d.x < 0.0);
+            (-d.y * SUBPIXEL_SCALE, d.x * SUBPIXEL_SCALE, is_top_left)
+        };
+        let edges = [edge(b, c), edge(c, a), edge(a, b)];
+        Self::from_snapped(
+            [a, b, c],
+            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x),
+            Vec3::from_array(edges.map(|e| e.0)),
+            Vec3::from_array(edges.map(|e| e.1)),
+            BVec3::from_array(edges.map(|e| e.2)),
+            msaa,
+        )
+    }
 
-            x = min_x + ((max_x - min_x) >> 1);
+    /// Finishes the setup from the vertices snapped to the sub pixel grid, double their signed
+    /// area, the steps of the edge functions and which edges are top or left edges. They are
+    /// computed in `f32`, where everything but the area is exact within [`MAX_COORDINATE`].
+    fn from_snapped(
+        [a, b, c]: [Vec2; 3],
+        abc: f32,
+        step_x: Vec3,
+        step_y: Vec3,
+        is_top_left: BVec3,
+        msaa: Msaa,
+    ) -> Self {
+        let step_x = step_x.as_i64vec3();
+        let step_y = step_y.as_i64vec3();
+        // The edge functions are linear so the offsets to the samples follow from the steps
+        let samples = sample_positions(msaa);
+        let mut sample_deltas = [I64Vec3::ZERO; MAX_SAMPLES];
+        for (delta, sample) in sample_deltas.iter_mut().zip(samples) {
+            // Sample positions are in 1/16th of a pixel from the top left corner
+            let offset = (sample.as_i64vec2() << (SUBPIXEL_BITS - 4)) - (1 << (SUBPIXEL_BITS - 1));
+            *delta = (step_x * offset.x + step_y * offset.y) >> SUBPIXEL_BITS;
         }
-        let mut x = bottom_start_x as i32;
-        let mut y = max.y as i32;
-        loop {
-            let mut min_x = min.x as i32;
-            let mut max_x = max.x as i32;
-            // WARN this isn't inclusive so I have to handroll it :(
-            // for x in (min_x..x).rev().step_by(BLOCK_SIZE as usize) {
-            let mut x_loop = x - BLOCK_SIZE;
-            loop {
-                if !draw_block(self, x_loop, y) {
-                    min_x = x_loop + BLOCK_SIZE;
-                    break;
-                }
-                if x_loop < min.x as i32 {
-                    break;
-                }
-                x_loop -= BLOCK_SIZE;
-            }
-            for x in (x..=max.x as i32 + BLOCK_SIZE).step_by(BLOCK_SIZE as usize) {
-                if !draw_block(self, x, y) {
-                    max_x = x;
-                    break;
-                }
-            }
+        Self {
+            a: a.as_i64vec2(),
+            b: b.as_i64vec2(),
+            c: c.as_i64vec2(),
+            abc,
+            bias: I64Vec3::select(is_top_left, I64Vec3::ZERO, I64Vec3::ONE),
+            step_x,
+            step_y,
+            sample_deltas,
+            sample_count: samples.len(),
+        }
+    }
 
-            // let min_start = Vec3::new(min_x as f32, y as f32, 0.0);
-            // let max_start = Vec3::new(max_x as f32, y as f32, 0.0);
-            // let end_offset = Vec3::new(0.0, BLOCK_SIZE as f32, 0.0);
-            // self.draw_line(min_start, min_start + end_offset, [0xff, 0xff, 0, 0]);
-            // self.draw_line(max_start, max_start + end_offset, [0xff, 0, 0xff, 0]);
+    // returns double the signed area of the triangle
+    #[inline(always)]
+    fn edge_function(a: I64Vec2, b: I64Vec2, c: I64Vec2) -> i64 {
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    }
 
-            y -= BLOCK_SIZE;
-            if y < half_y {
-                break;
+    /// Evaluates the edge functions `(bcp, cap, abp)` at the center of the pixel
+    #[inline(always)]
+    fn evaluate(&self, pixel: IVec2) -> I64Vec3 {
+        let half = 1 << (SUBPIXEL_BITS - 1);
+        self.evaluate_at((pixel.as_i64vec2() << SUBPIXEL_BITS) + half)
+    }
+
+    /// Evaluates the edge functions `(bcp, cap, abp)` at a point on the sub pixel grid
+    #[inline(always)]
+    fn evaluate_at(&self, p: I64Vec2) -> I64Vec3 {
+        I64Vec3::new(
+            Self::edge_function(self.b, self.c, p),
+            Self::edge_function(self.c, self.a, p),
+            Self::edge_function(self.a, self.b, p),
+        )
+    }
+
+    /// Returns false if no sample of the square block of `size` pixels is inside the triangle,
+    /// from the edge functions at the center of its first pixel
+    #[inline(always)]
+    fn overlaps_block(&self, edges: I64Vec3, size: i32) -> bool {
+        // The edge functions are linear so their smallest value over the block is at one of its
+        // corners, and the samples of a pixel are always between its corners
+        let corner = edges - (self.step_x + self.step_y) / 2;
+        let smallest = corner
+            + (self.step_x * size as i64).min(I64Vec3::ZERO)
+            + (self.step_y * size as i64).min(I64Vec3::ZERO);
+        (smallest + self.bias).cmple(I64Vec3::ZERO).all()
+    }
+
+    #[inline(always)]
+    fn is_inside(&self, edges: I64Vec3) -> bool {
+        let biased = edges + self.bias;
+        biased.x <= 0 && biased.y <= 0 && biased.z <= 0
+    }
+
+    /// Returns a mask of the samples of a pixel that are inside the triangle from the edge
+    /// functions at its center
+    #[inline(always)]
+    fn coverage(&self, edges: I64Vec3) -> u32 {
+        let mut coverage = 0;
+        for (i, delta) in self.sample_deltas[..self.sample_count].iter().enumerate() {
+            if self.is_inside(edges + delta) {
+                coverage |= 1 << i;
             }
-            x = min_x + ((max_x - min_x) >> 1);
         }
+        coverage
+    }
+
+    /// Returns the barycentric weights of the pixel center from its edge functions
+    #[inline(always)]
+    fn barycentric(&self, edges: I64Vec3) -> Vec3A {
+        edges.as_vec3a() / self.abc
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
     pub pos: Vec3,
-    pub color: Vec3,
+    /// `1 / w` of the vertex before the perspective divide, used for perspective correct
+    /// interpolation. Vertices that are already in canvas space should use `1.0`.
+    pub inv_w: f32,
+    pub varyings: Varyings,
 }
 
 impl Vertex {
+    /// Creates a vertex with its color stored as linear rgba in the varyings
     pub fn new(pos: Vec3, color: Color) -> Self {
+        Self::with_varyings(pos, Varyings::from_color(color))
+    }
+
+    pub fn with_varyings(pos: Vec3, varyings: Varyings) -> Self {
         Self {
-            pos: pos,
-            color: color.to_linear().to_vec3(),
+            pos,
+            inv_w: 1.0,
+            varyings,
         }
     }
 }
@@ -546,15 +1380,521 @@ impl Triangle {
         self.aabb = aabb;
     }
 
-    pub fn is_visible(&self) -> bool {
-        fn edge_function(a: Vec2, b: Vec2, c: Vec2) -> f32 {
-            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
-        }
+    /// Returns double the signed area of the triangle in canvas space.
+    ///
+    /// Since the canvas is y down, the area is negative when the triangle is counter clockwise.
+    pub fn signed_area(&self) -> f32 {
         let a = self.vertices[0].pos.xy();
         let b = self.vertices[1].pos.xy();
         let c = self.vertices[2].pos.xy();
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    }
+
+    /// Returns the largest screen space derivative of the depth across the triangle, the slope
+    /// used by [`wgpu::DepthBiasState::slope_scale`].
+    pub fn depth_slope(&self) -> f32 {
+        let [a, b, c] = self.vertices.map(|v| v.pos);
+        let (ab, ac) = (b - a, c - a);
+        let area = self.signed_area();
+        if area == 0.0 {
+            return 0.0;
+        }
+        let ddx = (ab.z * ac.y - ac.z * ab.y) / area;
+        let ddy = (ac.z * ab.x - ab.z * ac.x) / area;
+        ddx.abs().max(ddy.abs())
+    }
 
-        let abc = edge_function(a, b, c);
-        abc < 0.0
+    pub fn is_front_facing(&self, front_face: FrontFace) -> bool {
+        let is_ccw = self.signed_area() < 0.0;
+        match front_face {
+            FrontFace::Ccw => is_ccw,
+            FrontFace::Cw => !is_ccw,
+        }
+    }
+
+    /// Returns false if the triangle is degenerate or culled by the `state`
+    pub fn is_visible(&self, state: &DrawState) -> bool {
+        if self.signed_area() == 0.0 {
+            return false;
+        }
+        match state.cull_mode {
+            None => true,
+            Some(Face::Back) => self.is_front_facing(state.front_face),
+            Some(Face::Front) => !self.is_front_facing(state.front_face),
+        }
+    }
+
+    /// Returns the vertices in counter clockwise order, which is the only winding the
+    /// rasterizers handle. Clockwise triangles get their last two vertices swapped.
+    pub(crate) fn ccw_vertices(&self) -> [Vertex; 3] {
+        let [a, b, c] = self.vertices;
+        if self.signed_area() > 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use wgpu::CompareFunction;
+
+    use super::*;
+    use crate::{msaa::sample_offsets, shader::VertexColor};
+
+    /// Buffers of a canvas that isn't part of a [`crate::GlaciersContext`]
+    pub(crate) struct TestCanvas {
+        pub(crate) color: Vec<u8>,
+        depth: Vec<f32>,
+        pub(crate) samples: Option<Vec<u8>>,
+        size: UVec2,
+        msaa: Msaa,
+    }
+
+    impl TestCanvas {
+        pub(crate) fn new(size: UVec2, msaa: Msaa) -> Self {
+            let pixels = (size.x * size.y) as usize;
+            let samples = pixels * msaa.samples() as usize;
+            let format = PixelFormat::Rgba8Unorm;
+            Self {
+                color: vec![0; pixels * format.size()],
+                depth: vec![f32::INFINITY; samples],
+                samples: (msaa != Msaa::Off).then(|| vec![0; samples * format.size()]),
+                size,
+                msaa,
+            }
+        }
+
+        pub(crate) fn canvas(&mut self) -> GlaciersCanvas<'_> {
+            GlaciersCanvas {
+                color: &mut self.color,
+                depth: &mut self.depth,
+                samples: self.samples.as_deref_mut(),
+                size: self.size,
+                rows: 0..self.size.y,
+                msaa: self.msaa,
+                depth_clear_value: f32::INFINITY,
+                sample_offsets: sample_offsets(self.msaa),
+                format: PixelFormat::Rgba8Unorm,
+                sample_format: PixelFormat::Rgba8Unorm,
+                tonemapping: TonemapOperator::None,
+                exposure: 0.0,
+                viewport: Viewport {
+                    physical_position: UVec2::ZERO,
+                    physical_size: self.size,
+                    depth: 0.0..1.0,
+                },
+                scissor: URect::from_corners(UVec2::ZERO, self.size),
+                rasterizer: RasterizerStrategy::Auto,
+                assert_bounds: true,
+                show_box_outlines: false,
+                primitive: Primitive::None,
+                resolve_on_drop: true,
+            }
+        }
+    }
+
+    /// Triangles of every size with random depths and colors, some of them crossing the edges
+    /// of a canvas of `size` pixels
+    pub(crate) fn random_triangles(seed: u64, size: UVec2, count: usize) -> Vec<Triangle> {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut random = move || rng.f32();
+        (0..count)
+            .map(|i| {
+                let center = Vec2::new(random(), random()) * (size.as_vec2() + 40.0) - 20.0;
+                let radius = if i % 10 == 0 {
+                    150.0
+                } else {
+                    random() * 40.0 + 1.0
+                };
+                Triangle::new(std::array::from_fn(|_| {
+                    let offset = Vec2::new(random(), random()) * 2.0 - 1.0;
+                    let color = Color::linear_rgb(random(), random(), random());
+                    Vertex::new((center + offset * radius).extend(random()), color)
+                }))
+            })
+            .collect()
+    }
+
+    /// Shades every fragment with the same color, so the pixels only depend on the coverage
+    struct Solid(Vec4);
+
+    impl FragmentShader for Solid {
+        fn shade(&self, _fragment: &Fragment) -> Option<Vec4> {
+            Some(self.0)
+        }
+    }
+
+    /// Triangle covering a canvas of up to 100x100 pixels at `depth`
+    fn cover(depth: f32, color: Color) -> Triangle {
+        Triangle::new(
+            [(-1.0, -1.0), (250.0, -1.0), (-1.0, 250.0)]
+                .map(|(x, y)| Vertex::new(Vec3::new(x, y, depth), color)),
+        )
+    }
+
+    #[test]
+    fn depth_compare_and_write_mask() {
+        let (red, lime, blue) = (
+            Color::linear_rgb(1.0, 0.0, 0.0),
+            Color::linear_rgb(0.0, 1.0, 0.0),
+            Color::linear_rgb(0.0, 0.0, 1.0),
+        );
+        let mut target = TestCanvas::new(UVec2::new(8, 8), Msaa::Off);
+        let mut draw = |depth: f32, color: Color, state: DepthState| {
+            let state = DrawState {
+                depth: state,
+                cull_mode: None,
+                ..default()
+            };
+            target
+                .canvas()
+                .draw_triangle(&cover(depth, color), &state, &VertexColor);
+            // The interpolated depth can be off by a rounding error
+            let stored = (target.depth[0] * 1e4).round() / 1e4;
+            (target.color[..4].to_vec(), stored)
+        };
+        let no_write = DepthState {
+            write_enabled: false,
+            ..DepthState::DEFAULT
+        };
+        let greater = DepthState {
+            compare: CompareFunction::Greater,
+            ..DepthState::DEFAULT
+        };
+
+        assert_eq!(
+            draw(0.5, red, DepthState::DEFAULT),
+            (vec![255, 0, 0, 255], 0.5)
+        );
+        // Behind
+        assert_eq!(
+            draw(0.7, lime, DepthState::DEFAULT),
+            (vec![255, 0, 0, 255], 0.5)
+        );
+        // In front but without writing its depth, so the next draw still passes
+        assert_eq!(draw(0.3, blue, no_write), (vec![0, 0, 255, 255], 0.5));
+        assert_eq!(
+            draw(0.4, lime, DepthState::DEFAULT),
+            (vec![0, 255, 0, 255], 0.4)
+        );
+        assert_eq!(draw(0.2, red, greater), (vec![0, 255, 0, 255], 0.4));
+        assert_eq!(draw(0.6, red, greater), (vec![255, 0, 0, 255], 0.6));
+    }
+
+    #[test]
+    fn depth_clear_value() {
+        let mut target = TestCanvas::new(UVec2::new(8, 8), Msaa::Sample4);
+        let mut canvas = target.canvas();
+        canvas.set_depth_clear_value(0.0);
+        canvas.clear();
+        // Reversed depth, only fragments in front of the far plane at 0 pass
+        let state = DrawState {
+            depth: DepthState {
+                compare: CompareFunction::Greater,
+                ..DepthState::DEFAULT
+            },
+            cull_mode: None,
+            ..default()
+        };
+        canvas.draw_triangle(&cover(0.0, Color::WHITE), &state, &VertexColor);
+        drop(canvas);
+        assert!(target.depth.iter().all(|&depth| depth == 0.0));
+        assert!(target.color.iter().all(|&byte| byte == 0));
+
+        let mut canvas = target.canvas();
+        canvas.set_depth_clear_value(0.0);
+        canvas.draw_triangle(&cover(0.5, Color::WHITE), &state, &VertexColor);
+        drop(canvas);
+        assert!(target.depth.iter().all(|&depth| depth == 0.5));
+        assert!(target.color.iter().all(|&byte| byte == 255));
+    }
+
+    #[test]
+    fn culling() {
+        // Counter clockwise with the y axis pointing up like in wgpu
+        let ccw = Triangle::new(
+            [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)]
+                .map(|(x, y)| Vertex::new(Vec3::new(x, 10.0 - y, 0.5), Color::WHITE)),
+        );
+        let visible = |front_face, cull_mode| {
+            ccw.is_visible(&DrawState {
+                front_face,
+                cull_mode,
+                ..default()
+            })
+        };
+        assert!(visible(FrontFace::Ccw, None));
+        assert!(visible(FrontFace::Ccw, Some(Face::Back)));
+        assert!(!visible(FrontFace::Ccw, Some(Face::Front)));
+        assert!(visible(FrontFace::Cw, None));
+        assert!(!visible(FrontFace::Cw, Some(Face::Back)));
+        assert!(visible(FrontFace::Cw, Some(Face::Front)));
+    }
+
+    #[test]
+    fn setup_x8_matches_setup() {
+        let size = UVec2::new(157, 141);
+        // Not a multiple of 8 so the last lanes are empty
+        let triangles = random_triangles(3, size, 61);
+        let mut target = TestCanvas::new(size, Msaa::Sample4);
+        let canvas = target.canvas();
+        for front_face in [FrontFace::Ccw, FrontFace::Cw] {
+            for cull_mode in [None, Some(Face::Back), Some(Face::Front)] {
+                let state = DrawState {
+                    front_face,
+                    cull_mode,
+                    ..default()
+                };
+                for chunk in triangles.chunks(8) {
+                    let setups = TriangleSetup::new_x8(&canvas, chunk, &state);
+                    assert!(setups[chunk.len()..].iter().all(Option::is_none));
+                    for (triangle, setup_x8) in chunk.iter().zip(&setups) {
+                        let setup = TriangleSetup::new(&canvas, triangle, &state);
+                        assert_eq!(setup_x8.is_some(), setup.is_some());
+                        let (Some(setup_x8), Some(setup)) = (setup_x8, setup) else {
+                            continue;
+                        };
+                        assert!(triangle.is_visible(&state));
+                        let (a, b) = (&setup_x8.edges, &setup.edges);
+                        assert_eq!([a.a, a.b, a.c], [b.a, b.b, b.c]);
+                        assert_eq!(a.abc, b.abc);
+                        assert_eq!([a.bias, a.step_x, a.step_y], [b.bias, b.step_x, b.step_y]);
+                        assert_eq!(a.sample_deltas, b.sample_deltas);
+                        assert_eq!((setup_x8.min, setup_x8.max), (setup.min, setup.max));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_averages_the_samples() {
+        let mut target = TestCanvas::new(UVec2::new(2, 1), Msaa::Sample4);
+        let mut canvas = target.canvas();
+        let state = DrawState::OVERLAY;
+        // Half of the samples of the first pixel and a quarter of the second one are white
+        canvas.write_color(IVec2::ZERO, 0b0101, Vec4::ONE, &state);
+        canvas.write_color(IVec2::X, 0b1000, Vec4::ONE, &state);
+        drop(canvas);
+        assert_eq!(target.color, [128, 128, 128, 128, 64, 64, 64, 64]);
+    }
+
+    #[test]
+    fn triangles_beyond_the_max_coordinate_are_dropped() {
+        let size = UVec2::new(64, 48);
+        let triangle = |positions: [(f32, f32); 3]| {
+            Triangle::new(positions.map(|(x, y)| Vertex::new(Vec3::new(x, y, 0.5), Color::WHITE)))
+        };
+        let huge = triangle([(30.0, 30.0), (1e8, 30.0), (30.0, 1e8)]);
+        let large = triangle([(-4000.0, -100.0), (4000.0, -100.0), (-100.0, 4000.0)]);
+        let state = DrawState {
+            cull_mode: None,
+            ..default()
+        };
+        for rasterizer in [
+            RasterizerStrategy::Scalar,
+            RasterizerStrategy::ScalarBox,
+            RasterizerStrategy::Wide,
+            RasterizerStrategy::WideBox,
+        ] {
+            let mut target = TestCanvas::new(size, Msaa::Sample4);
+            let mut canvas = target.canvas();
+            canvas.rasterizer = rasterizer;
+            canvas.draw_triangle(&huge, &state, &VertexColor);
+            canvas.draw_triangles(&[huge], &state, &VertexColor);
+            drop(canvas);
+            assert!(target.color.iter().all(|&byte| byte == 0), "{rasterizer:?}");
+
+            let mut canvas = target.canvas();
+            canvas.rasterizer = rasterizer;
+            canvas.draw_triangle(&large, &state, &VertexColor);
+            drop(canvas);
+            assert!(
+                target.color.iter().all(|&byte| byte == 255),
+                "{rasterizer:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn triangles_are_clipped_to_the_scissor() {
+        let size = UVec2::new(157, 141);
+        let scissor = URect::new(20, 30, 100, 90);
+        let triangles = random_triangles(4, size, 100);
+        let state = DrawState {
+            cull_mode: None,
+            ..default()
+        };
+        for rasterizer in [
+            RasterizerStrategy::Scalar,
+            RasterizerStrategy::ScalarBox,
+            RasterizerStrategy::Wide,
+            RasterizerStrategy::WideBox,
+        ] {
+            let mut target = TestCanvas::new(size, Msaa::Sample4);
+            let mut canvas = target.canvas();
+            canvas.rasterizer = rasterizer;
+            canvas.scissor = scissor;
+            // Writes outside of the scissor rectangle panic since `assert_bounds` is set
+            for triangle in &triangles {
+                canvas.draw_triangle(triangle, &state, &VertexColor);
+            }
+            canvas.draw_triangles_parallel(&triangles, &state, &VertexColor);
+            drop(canvas);
+            let mut inside = 0;
+            for (i, pixel) in target.color.chunks_exact(4).enumerate() {
+                let pos = UVec2::new(i as u32 % size.x, i as u32 / size.x);
+                if pos.cmpge(scissor.min).all() && pos.cmplt(scissor.max).all() {
+                    inside += (pixel[3] != 0) as usize;
+                } else {
+                    assert_eq!(pixel[3], 0, "{rasterizer:?} {pos}");
+                }
+            }
+            assert!(inside > 0, "{rasterizer:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Out of bounds write")]
+    fn out_of_bounds_writes_panic() {
+        let mut target = TestCanvas::new(UVec2::new(8, 8), Msaa::Off);
+        target
+            .canvas()
+            .draw_point(IVec2::new(8, 0), Color::WHITE, &DrawState::OVERLAY);
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        let center = Vec2::new(50.3, 49.7);
+        let fan: Vec<Vec2> = (0..13)
+            .map(|i| center + Vec2::from_angle(i as f32 * 0.5) * (30.0 + i as f32))
+            .collect();
+        let mut triangles: Vec<[Vec2; 3]> = fan
+            .iter()
+            .zip(fan.iter().cycle().skip(1))
+            .map(|(&a, &b)| [center, a, b])
+            .collect();
+        // Edges going through pixel centers and samples, where the top-left rule breaks ties
+        let [a, b, c, d] = [(100, 4), (132, 4), (132, 36), (100, 36)]
+            .map(|(x, y)| Vec2::new(x as f32, y as f32) + 0.5);
+        triangles.extend([[a, b, c], [a, c, d]]);
+
+        let state = DrawState {
+            depth: DepthState::DISABLED,
+            cull_mode: None,
+            blend: Some(crate::blend::ADDITIVE),
+            ..default()
+        };
+        let color = Color::linear_rgba(0.25, 0.25, 0.25, 0.25);
+        let strategies = [
+            RasterizerStrategy::Scalar,
+            RasterizerStrategy::ScalarBox,
+            RasterizerStrategy::Wide,
+            RasterizerStrategy::WideBox,
+        ];
+        for msaa in [Msaa::Off, Msaa::Sample4] {
+            for rasterizer in strategies {
+                let mut target = TestCanvas::new(UVec2::new(140, 100), msaa);
+                let mut canvas = target.canvas();
+                canvas.rasterizer = rasterizer;
+                for positions in &triangles {
+                    let vertices = positions.map(|pos| Vertex::new(pos.extend(0.5), color));
+                    canvas.draw_triangle(&Triangle::new(vertices), &state, &VertexColor);
+                }
+                drop(canvas);
+
+                // A single write stores 64 in every channel, two writes 128
+                let pixels = target.samples.as_ref().unwrap_or(&target.color);
+                let written = pixels.iter().filter(|&&byte| byte == 64).count();
+                assert!(written > 0, "{rasterizer:?} {msaa:?}");
+                assert!(
+                    pixels.iter().all(|&byte| byte == 0 || byte == 64),
+                    "{rasterizer:?} {msaa:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rasterizers_draw_the_same_pixels() {
+        let size = UVec2::new(157, 141);
+        let triangles = random_triangles(2, size, 200);
+        // The wide rasterizers interpolate in `f32x8`, so only the coverage is compared
+        let state = DrawState {
+            depth: DepthState::DISABLED,
+            cull_mode: None,
+            ..default()
+        };
+        for msaa in [Msaa::Off, Msaa::Sample4] {
+            let draw = |rasterizer| {
+                let mut target = TestCanvas::new(size, msaa);
+                let mut canvas = target.canvas();
+                canvas.rasterizer = rasterizer;
+                for (i, triangle) in triangles.iter().enumerate() {
+                    let color = Vec4::new(i as f32 / 200.0, 0.5, 1.0, 1.0);
+                    canvas.draw_triangle(triangle, &state, &Solid(color));
+                }
+                drop(canvas);
+                target.color
+            };
+            let scalar = draw(RasterizerStrategy::Scalar);
+            for rasterizer in [
+                RasterizerStrategy::ScalarBox,
+                RasterizerStrategy::Wide,
+                RasterizerStrategy::WideBox,
+            ] {
+                assert!(draw(rasterizer) == scalar, "{rasterizer:?} {msaa:?}");
+            }
+        }
+    }
+
+    /// Times every rasterizer on the scene of the `many_triangles` example at 1280x720. Run it
+    /// with `cargo test --release -- --ignored --nocapture bench_rasterizers`
+    #[test]
+    #[ignore]
+    fn bench_rasterizers() {
+        let size = UVec2::new(1280, 720);
+        let max_size = size.x / 5;
+        let state = DrawState {
+            depth: DepthState::DISABLED,
+            ..default()
+        };
+        let mut rng = fastrand::Rng::with_seed(42);
+        let triangles: Vec<_> = std::iter::repeat_with(|| {
+            let color = Color::srgb(rng.f32(), rng.f32(), rng.f32());
+            let x = rng.u32(0..size.x - max_size);
+            let y = rng.u32(0..size.y - max_size);
+            Triangle::new(std::array::from_fn(|_| {
+                let pos = UVec2::new(x + rng.u32(0..max_size), y + rng.u32(0..max_size));
+                Vertex::new(pos.as_vec2().extend(1.0), color)
+            }))
+        })
+        .filter(|triangle| triangle.is_visible(&state))
+        .take(1000)
+        .collect();
+
+        let mut target = TestCanvas::new(size, Msaa::Off);
+        let mut canvas = target.canvas();
+        for rasterizer in [
+            RasterizerStrategy::Scalar,
+            RasterizerStrategy::ScalarBox,
+            RasterizerStrategy::Wide,
+            RasterizerStrategy::WideBox,
+        ] {
+            canvas.rasterizer = rasterizer;
+            let mut best = std::time::Duration::MAX;
+            for _ in 0..15 {
+                canvas.clear();
+                let start = std::time::Instant::now();
+                for triangle in &triangles {
+                    canvas.draw_triangle(triangle, &state, &VertexColor);
+                }
+                best = best.min(start.elapsed());
+            }
+            println!("{rasterizer:?}: {best:.2?}");
+        }
     }
 }
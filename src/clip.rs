@@ -0,0 +1,340 @@
+use bevy::{camera::Viewport, prelude::*};
+
+use crate::{
+    canvas::{MAX_COORDINATE, Triangle, Vertex},
+    varyings::Varyings,
+};
+
+/// Default size of the guard band, in multiples of the viewport.
+///
+/// Triangles that only poke slightly outside of the viewport aren't clipped on x and y, the
+/// rasterizer simply skips the pixels outside of the canvas. This is a lot cheaper than clipping.
+pub const DEFAULT_GUARD_BAND: f32 = 2.0;
+
+/// A triangle can gain at most one vertex per clipping plane
+const MAX_CLIPPED_VERTICES: usize = 3 + 6;
+
+/// A vertex in homogeneous clip space, before the perspective divide
+#[derive(Clone, Copy, Debug)]
+pub struct ClipVertex {
+    pub pos: Vec4,
+    pub varyings: Varyings,
+}
+
+impl ClipVertex {
+    /// Creates a vertex with its color stored as linear rgba in the varyings
+    pub fn new(pos: Vec4, color: Color) -> Self {
+        Self::with_varyings(pos, Varyings::from_color(color))
+    }
+
+    pub fn with_varyings(pos: Vec4, varyings: Varyings) -> Self {
+        Self { pos, varyings }
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            pos: self.pos.lerp(other.pos, t),
+            varyings: self.varyings.lerp(&other.varyings, t),
+        }
+    }
+
+    /// Applies the perspective divide and maps the vertex to a viewport of the canvas.
+    ///
+    /// The resulting depth is the ndc depth mapped to the depth range of the viewport. Bevy uses
+    /// reversed depth so the closest fragment has the biggest depth.
+    pub fn to_canvas(&self, viewport: &Viewport) -> Vertex {
+        let ndc = self.pos.xyz() / self.pos.w;
+        let position = viewport.physical_position.as_vec2();
+        let size = viewport.physical_size.as_vec2();
+        let depth = &viewport.depth;
+        let pos = Vec3::new(
+            position.x + (ndc.x * 0.5 + 0.5) * size.x,
+            position.y + (0.5 - ndc.y * 0.5) * size.y,
+            depth.start + ndc.z * (depth.end - depth.start),
+        );
+        Vertex {
+            pos,
+            inv_w: 1.0 / self.pos.w,
+            varyings: self.varyings,
+        }
+    }
+}
+
+/// Clipping planes in clip space. A point is inside a plane when `plane.dot(pos) >= 0`.
+///
+/// Depth is clipped to `0 <= z <= w` which works for both regular and reversed depth.
+fn clip_planes(guard_band: f32) -> [Vec4; 6] {
+    [
+        // near/far
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(0.0, 0.0, -1.0, 1.0),
+        // guard band
+        Vec4::new(1.0, 0.0, 0.0, guard_band),
+        Vec4::new(-1.0, 0.0, 0.0, guard_band),
+        Vec4::new(0.0, 1.0, 0.0, guard_band),
+        Vec4::new(0.0, -1.0, 0.0, guard_band),
+    ]
+}
+
+/// Clips a triangle against the near and far planes and the guard band using
+/// Sutherland–Hodgman and returns the resulting triangles mapped to the viewport.
+///
+/// The guard band is shrunk if needed so the vertices stay within [`MAX_COORDINATE`].
+pub fn clip_triangle(
+    vertices: &[ClipVertex; 3],
+    viewport: &Viewport,
+    guard_band: f32,
+) -> ClippedTriangles {
+    let mut clipped = ClippedTriangles {
+        vertices: [vertices[0]; MAX_CLIPPED_VERTICES],
+        len: 0,
+        next: 1,
+        viewport: viewport.clone(),
+    };
+
+    // With a pixel of margin for the rounding errors of the clipping
+    let position = viewport.physical_position.as_vec2();
+    let size = viewport.physical_size.as_vec2();
+    let max_guard_band = 2.0 * (MAX_COORDINATE - 1.0 - position) / size - 1.0;
+    let guard_band = guard_band.min(max_guard_band.min_element());
+    let planes = clip_planes(guard_band);
+    let distances = vertices.map(|v| planes.map(|plane| plane.dot(v.pos)));
+
+    // Trivially reject triangles that are fully outside of a single plane
+    let outside = (0..planes.len()).any(|p| distances.iter().all(|d| d[p] < 0.0));
+    if outside {
+        return clipped;
+    }
+
+    clipped.vertices[..3].copy_from_slice(vertices);
+    clipped.len = 3;
+
+    // Trivially accept triangles that are inside of every plane
+    if distances.iter().flatten().all(|d| *d >= 0.0) {
+        return clipped;
+    }
+
+    let mut scratch = [vertices[0]; MAX_CLIPPED_VERTICES];
+    for plane in planes {
+        let input = &clipped.vertices[..clipped.len];
+        let mut len = 0;
+        for (i, current) in input.iter().enumerate() {
+            let previous = &input[(i + input.len() - 1) % input.len()];
+            let current_distance = plane.dot(current.pos);
+            let previous_distance = plane.dot(previous.pos);
+
+            if (current_distance >= 0.0) != (previous_distance >= 0.0) {
+                let t = previous_distance / (previous_distance - current_distance);
+                scratch[len] = previous.lerp(current, t);
+                len += 1;
+            }
+            if current_distance >= 0.0 {
+                scratch[len] = *current;
+                len += 1;
+            }
+        }
+        clipped.vertices[..len].copy_from_slice(&scratch[..len]);
+        clipped.len = len;
+        if len < 3 {
+            clipped.len = 0;
+            break;
+        }
+    }
+
+    clipped
+}
+
+/// Clips the segment from `start` to `end` against `rect` using Liang–Barsky.
+///
+/// Returns the interpolation factors of the clipped endpoints along the segment so attributes
+/// can be clipped too, or `None` if the segment is entirely outside.
+pub fn clip_line(start: Vec2, end: Vec2, rect: Rect) -> Option<(f32, f32)> {
+    let delta = end - start;
+    let mut t0: f32 = 0.0;
+    let mut t1: f32 = 1.0;
+    // For each side, the segment enters the rect when `p < 0` and leaves it when `p > 0`
+    for (p, q) in [
+        (-delta.x, start.x - rect.min.x),
+        (delta.x, rect.max.x - start.x),
+        (-delta.y, start.y - rect.min.y),
+        (delta.y, rect.max.y - start.y),
+    ] {
+        if p == 0.0 {
+            // Parallel to the side, either fully inside or fully outside of it
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+/// Triangles produced by [`clip_triangle`]. The clipped polygon is always convex so it's
+/// triangulated as a fan.
+pub struct ClippedTriangles {
+    vertices: [ClipVertex; MAX_CLIPPED_VERTICES],
+    len: usize,
+    next: usize,
+    viewport: Viewport,
+}
+
+impl Iterator for ClippedTriangles {
+    type Item = Triangle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next + 1 >= self.len {
+            return None;
+        }
+        let triangle = Triangle::new([
+            self.vertices[0].to_canvas(&self.viewport),
+            self.vertices[self.next].to_canvas(&self.viewport),
+            self.vertices[self.next + 1].to_canvas(&self.viewport),
+        ]);
+        self.next += 1;
+        Some(triangle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport() -> Viewport {
+        Viewport {
+            physical_position: UVec2::ZERO,
+            physical_size: UVec2::new(100, 50),
+            depth: 0.0..1.0,
+        }
+    }
+
+    #[test]
+    fn line_inside_is_kept() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let clipped = clip_line(Vec2::new(1.0, 2.0), Vec2::new(9.0, 3.0), rect);
+        assert_eq!(clipped, Some((0.0, 1.0)));
+    }
+
+    #[test]
+    fn line_crossing_is_clipped() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let clipped = clip_line(Vec2::new(-10.0, 5.0), Vec2::new(20.0, 5.0), rect);
+        let (t0, t1) = clipped.unwrap();
+        assert!((t0 - 1.0 / 3.0).abs() < 1e-6);
+        assert!((t1 - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn line_outside_is_rejected() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        // Parallel to the top side, above it
+        assert_eq!(
+            clip_line(Vec2::new(1.0, -1.0), Vec2::new(9.0, -1.0), rect),
+            None
+        );
+        // Crosses the lines of two sides but not the rect
+        assert_eq!(
+            clip_line(Vec2::new(-5.0, 6.0), Vec2::new(4.0, 15.0), rect),
+            None
+        );
+    }
+
+    #[test]
+    fn triangle_inside_is_mapped_to_the_viewport() {
+        let vertices = [
+            Vec4::new(-1.0, 1.0, 0.5, 1.0),
+            Vec4::new(1.0, 1.0, 0.5, 1.0),
+            Vec4::new(1.0, -1.0, 0.5, 2.0),
+        ]
+        .map(|pos| ClipVertex::new(pos, Color::WHITE));
+        let triangles: Vec<_> = clip_triangle(&vertices, &viewport(), DEFAULT_GUARD_BAND).collect();
+        assert_eq!(triangles.len(), 1);
+        let positions = triangles[0].vertices.map(|v| v.pos);
+        assert_eq!(
+            positions,
+            [
+                Vec3::new(0.0, 0.0, 0.5),
+                Vec3::new(100.0, 0.0, 0.5),
+                Vec3::new(75.0, 37.5, 0.25),
+            ]
+        );
+    }
+
+    #[test]
+    fn triangle_outside_is_rejected() {
+        // Behind the near plane
+        let vertices = [
+            Vec4::new(-1.0, 1.0, -0.5, 1.0),
+            Vec4::new(1.0, 1.0, -0.5, 1.0),
+            Vec4::new(1.0, -1.0, -0.5, 1.0),
+        ]
+        .map(|pos| ClipVertex::new(pos, Color::WHITE));
+        assert_eq!(
+            clip_triangle(&vertices, &viewport(), DEFAULT_GUARD_BAND).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn triangle_crossing_the_near_plane_is_split() {
+        // The first vertex is behind the near plane, cutting a corner makes a quad
+        let vertices = [
+            Vec4::new(0.0, 1.0, -1.0, 1.0),
+            Vec4::new(1.0, -1.0, 1.0, 1.0),
+            Vec4::new(-1.0, -1.0, 1.0, 1.0),
+        ]
+        .map(|pos| ClipVertex::new(pos, Color::WHITE));
+        let triangles: Vec<_> = clip_triangle(&vertices, &viewport(), DEFAULT_GUARD_BAND).collect();
+        assert_eq!(triangles.len(), 2);
+        for triangle in triangles {
+            for vertex in triangle.vertices {
+                assert!((0.0..=1.0).contains(&vertex.pos.z));
+                assert!(vertex.pos.y >= 25.0);
+            }
+        }
+    }
+
+    #[test]
+    fn guard_band_clips_far_triangles() {
+        // Pokes 4 viewports to the right, outside of the guard band of 2
+        let vertices = [
+            Vec4::new(0.0, 0.0, 0.5, 1.0),
+            Vec4::new(5.0, 0.0, 0.5, 1.0),
+            Vec4::new(0.0, 1.0, 0.5, 1.0),
+        ]
+        .map(|pos| ClipVertex::new(pos, Color::WHITE));
+        let viewport = viewport();
+        let max_x = viewport.physical_size.x as f32 * (DEFAULT_GUARD_BAND + 1.0) / 2.0;
+        let triangles: Vec<_> = clip_triangle(&vertices, &viewport, DEFAULT_GUARD_BAND).collect();
+        assert!(!triangles.is_empty());
+        for triangle in triangles {
+            for vertex in triangle.vertices {
+                assert!(vertex.pos.x <= max_x + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn guard_band_stays_within_the_max_coordinate() {
+        let vertices = [
+            Vec4::new(0.0, 0.0, 0.5, 1.0),
+            Vec4::new(5.0, 0.0, 0.5, 1.0),
+            Vec4::new(0.0, 1.0, 0.5, 1.0),
+        ]
+        .map(|pos| ClipVertex::new(pos, Color::WHITE));
+        // Offset so the guard band of the viewport reaches further than the canvas
+        let viewport = Viewport {
+            physical_position: UVec2::new(256, 0),
+            physical_size: UVec2::new(3840, 2160),
+            depth: 0.0..1.0,
+        };
+        for triangle in clip_triangle(&vertices, &viewport, DEFAULT_GUARD_BAND) {
+            for vertex in triangle.vertices {
+                assert!(vertex.pos.x <= MAX_COORDINATE);
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use wgpu::{CompareFunction, DepthBiasState};
+
+use crate::msaa::SampleData;
+
+/// CPU side depth attachment of a [`GlaciersCanvas`](crate::canvas::GlaciersCanvas).
+///
+/// It's kept as its own asset instead of an [`Image`] because it never needs to be uploaded to
+/// the gpu and because we need to borrow it at the same time as the color image.
+///
+/// When multisampling is enabled every sample has its own depth.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct DepthBuffer {
+    pub(crate) samples: SampleData<f32>,
+    pub(crate) clear_value: f32,
+}
+
+impl DepthBuffer {
+    pub fn new(size: UVec2, msaa: Msaa) -> Self {
+        Self {
+            samples: SampleData::new(size, msaa, 1, f32::INFINITY),
+            clear_value: f32::INFINITY,
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.samples.size
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        self.samples.msaa
+    }
+
+    /// Resizes the buffer. The content is reset since it can't be meaningfully preserved.
+    pub fn resize(&mut self, size: UVec2) {
+        self.samples.resize(size, self.clear_value);
+    }
+
+    pub fn clear_value(&self) -> f32 {
+        self.clear_value
+    }
+
+    /// Sets the value used by [`DepthBuffer::clear`].
+    ///
+    /// This should be `f32::INFINITY` when using [`CompareFunction::Less`] and `0.0` for reversed
+    /// depth with [`CompareFunction::Greater`].
+    pub fn set_clear_value(&mut self, value: f32) {
+        self.clear_value = value;
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.data.fill(self.clear_value);
+    }
+
+    /// Returns the depth of the first sample of the pixel
+    pub fn get(&self, pos: UVec2) -> Option<f32> {
+        self.get_sample(pos, 0)
+    }
+
+    pub fn get_sample(&self, pos: UVec2, sample: usize) -> Option<f32> {
+        self.samples.get(pos, sample).map(|depth| depth[0])
+    }
+}
+
+/// Controls how a draw interacts with the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthState {
+    /// Comparison between the fragment depth and the stored depth. The fragment is kept when
+    /// `compare(fragment, stored)` is true.
+    pub compare: CompareFunction,
+    /// Whether fragments that pass the test write their depth.
+    pub write_enabled: bool,
+    /// Offset added to the depth of fragments before the test, used to draw wireframes and
+    /// decals on top of coplanar triangles. See [`DepthState::biased`].
+    pub bias: DepthBiasState,
+}
+
+impl DepthState {
+    pub const DEFAULT: Self = Self {
+        compare: CompareFunction::Less,
+        write_enabled: true,
+        bias: DepthBiasState {
+            constant: 0,
+            slope_scale: 0.0,
+            clamp: 0.0,
+        },
+    };
+
+    /// Always passes and never writes depth.
+    pub const DISABLED: Self = Self {
+        compare: CompareFunction::Always,
+        write_enabled: false,
+        ..Self::DEFAULT
+    };
+
+    /// Applies the depth bias to the depth of a fragment following the wgpu rules for floating
+    /// point depth formats. `slope` is the largest screen space derivative of the depth of the
+    /// primitive.
+    #[inline(always)]
+    pub fn biased(&self, depth: f32, slope: f32) -> f32 {
+        let DepthBiasState {
+            constant,
+            slope_scale,
+            clamp,
+        } = self.bias;
+        if constant == 0 && slope_scale == 0.0 {
+            return depth;
+        }
+        // The constant is in units of the smallest difference representable at the exponent
+        // of the depth
+        let exponent = f32::from_bits(depth.abs().to_bits() & 0x7f80_0000);
+        let bias = constant as f32 * exponent * f32::EPSILON + slope_scale * slope;
+        let bias = if clamp > 0.0 {
+            bias.min(clamp)
+        } else if clamp < 0.0 {
+            bias.max(clamp)
+        } else {
+            bias
+        };
+        depth + bias
+    }
+
+    #[inline(always)]
+    pub fn test(&self, depth: f32, stored: f32) -> bool {
+        match self.compare {
+            CompareFunction::Never => false,
+            CompareFunction::Less => depth < stored,
+            CompareFunction::Equal => depth == stored,
+            CompareFunction::LessEqual => depth <= stored,
+            CompareFunction::Greater => depth > stored,
+            CompareFunction::NotEqual => depth != stored,
+            CompareFunction::GreaterEqual => depth >= stored,
+            CompareFunction::Always => true,
+        }
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_functions() {
+        // Fragments in front of, at and behind the stored depth of 0.5
+        let cases = [
+            (CompareFunction::Never, [false, false, false]),
+            (CompareFunction::Less, [true, false, false]),
+            (CompareFunction::Equal, [false, true, false]),
+            (CompareFunction::LessEqual, [true, true, false]),
+            (CompareFunction::Greater, [false, false, true]),
+            (CompareFunction::NotEqual, [true, false, true]),
+            (CompareFunction::GreaterEqual, [false, true, true]),
+            (CompareFunction::Always, [true, true, true]),
+        ];
+        for (compare, expected) in cases {
+            let state = DepthState {
+                compare,
+                ..DepthState::DEFAULT
+            };
+            let passed = [0.25, 0.5, 0.75].map(|depth| state.test(depth, 0.5));
+            assert_eq!(passed, expected, "{compare:?}");
+        }
+    }
+
+    #[test]
+    fn clear_value() {
+        let mut buffer = DepthBuffer::new(UVec2::new(3, 2), Msaa::Sample4);
+        assert_eq!(buffer.get_sample(UVec2::new(2, 1), 3), Some(f32::INFINITY));
+        assert_eq!(buffer.get_sample(UVec2::new(3, 1), 0), None);
+        assert_eq!(buffer.get_sample(UVec2::new(2, 1), 4), None);
+
+        buffer.set_clear_value(0.0);
+        buffer.clear();
+        assert_eq!(buffer.get(UVec2::new(1, 1)), Some(0.0));
+        buffer.resize(UVec2::new(5, 4));
+        assert_eq!(buffer.size(), UVec2::new(5, 4));
+        assert_eq!(buffer.get_sample(UVec2::new(4, 3), 3), Some(0.0));
+    }
+}
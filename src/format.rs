@@ -0,0 +1,231 @@
+//! Pixel formats the canvas can draw to and textures can be sampled from, see [`PixelFormat`].
+
+use std::sync::LazyLock;
+
+use bevy::prelude::*;
+use half::f16;
+use wgpu::TextureFormat;
+
+/// Size in bytes of the largest supported pixel, see [`PixelFormat::Rgba32Float`]
+pub const MAX_PIXEL_SIZE: usize = 16;
+
+/// Decoded value of every 8 bit sRGB channel value
+static SRGB_TO_LINEAR: LazyLock<[f32; 256]> =
+    LazyLock::new(|| std::array::from_fn(|i| Srgba::gamma_function(i as f32 / 255.0)));
+
+/// Number of entries of [`LINEAR_TO_SRGB`]. It fits in the L1 cache, stays within one step of
+/// the exact conversion and every decoded 8 bit value encodes back to itself.
+const LINEAR_TO_SRGB_LEN: usize = 4096;
+
+/// Encoded 8 bit sRGB value of linear values evenly spaced between 0 and 1
+static LINEAR_TO_SRGB: LazyLock<[u8; LINEAR_TO_SRGB_LEN]> = LazyLock::new(|| {
+    std::array::from_fn(|i| {
+        let v = i as f32 / (LINEAR_TO_SRGB_LEN - 1) as f32;
+        (Srgba::gamma_function_inverse(v) * u8::MAX as f32).round() as u8
+    })
+});
+
+/// Encoding of the pixels of a canvas.
+///
+/// It's picked once from the [`TextureFormat`] of the canvas image so drawing doesn't need to
+/// match on every texture format for each pixel.
+///
+/// Colors are always linear outside of the pixels: they are interpolated, blended and resolved in
+/// linear space, then sRGB formats encode them with a lookup table when they are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8UnormSrgb,
+    Bgra8UnormSrgb,
+    Rgba8Unorm,
+    /// Only the red channel is stored, useful for grayscale images and masks. It is displayed in
+    /// grey by the [`GlaciersPlugin`](crate::plugin::GlaciersPlugin).
+    R8Unorm,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl PixelFormat {
+    /// Returns `None` if the format isn't supported
+    pub fn new(format: TextureFormat) -> Option<Self> {
+        match format {
+            TextureFormat::Rgba8UnormSrgb => Some(Self::Rgba8UnormSrgb),
+            TextureFormat::Bgra8UnormSrgb => Some(Self::Bgra8UnormSrgb),
+            TextureFormat::Rgba8Unorm => Some(Self::Rgba8Unorm),
+            TextureFormat::R8Unorm => Some(Self::R8Unorm),
+            TextureFormat::Rgba16Float => Some(Self::Rgba16Float),
+            TextureFormat::Rgba32Float => Some(Self::Rgba32Float),
+            _ => None,
+        }
+    }
+
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            Self::Rgba8UnormSrgb => TextureFormat::Rgba8UnormSrgb,
+            Self::Bgra8UnormSrgb => TextureFormat::Bgra8UnormSrgb,
+            Self::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+            Self::R8Unorm => TextureFormat::R8Unorm,
+            Self::Rgba16Float => TextureFormat::Rgba16Float,
+            Self::Rgba32Float => TextureFormat::Rgba32Float,
+        }
+    }
+
+    /// Whether the format can store colors brighter than 1, which need to be tonemapped before
+    /// being displayed
+    pub fn is_hdr(self) -> bool {
+        matches!(self, Self::Rgba16Float | Self::Rgba32Float)
+    }
+
+    /// Size of a pixel in bytes
+    #[inline(always)]
+    pub fn size(self) -> usize {
+        match self {
+            Self::Rgba8UnormSrgb | Self::Bgra8UnormSrgb | Self::Rgba8Unorm => 4,
+            Self::R8Unorm => 1,
+            Self::Rgba16Float => 8,
+            Self::Rgba32Float => 16,
+        }
+    }
+
+    /// Writes a linear color to the first [`PixelFormat::size`] bytes of `pixel`. Channels the
+    /// format doesn't have are dropped.
+    #[inline(always)]
+    pub fn encode(self, color: Vec4, pixel: &mut [u8]) {
+        let unorm = |v: f32| (v.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8;
+        // NaN ends up as 0 since the cast saturates
+        let srgb = |v: f32| {
+            LINEAR_TO_SRGB[(v.clamp(0.0, 1.0) * (LINEAR_TO_SRGB_LEN - 1) as f32 + 0.5) as usize]
+        };
+        match self {
+            Self::Rgba8UnormSrgb => {
+                let [r, g, b, a] = color.to_array();
+                pixel[..4].copy_from_slice(&[srgb(r), srgb(g), srgb(b), unorm(a)]);
+            }
+            Self::Bgra8UnormSrgb => {
+                let [r, g, b, a] = color.to_array();
+                pixel[..4].copy_from_slice(&[srgb(b), srgb(g), srgb(r), unorm(a)]);
+            }
+            Self::Rgba8Unorm => pixel[..4].copy_from_slice(&color.to_array().map(unorm)),
+            Self::R8Unorm => pixel[0] = unorm(color.x),
+            Self::Rgba16Float => {
+                for (bytes, v) in pixel.chunks_exact_mut(2).zip(color.to_array()) {
+                    bytes.copy_from_slice(&f16::from_f32(v).to_le_bytes());
+                }
+            }
+            Self::Rgba32Float => {
+                for (bytes, v) in pixel.chunks_exact_mut(4).zip(color.to_array()) {
+                    bytes.copy_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    /// Reads a linear color from a pixel, the inverse of [`PixelFormat::encode`]. Missing color
+    /// channels are 0 and a missing alpha is 1.
+    #[inline(always)]
+    pub fn decode(self, pixel: &[u8]) -> Vec4 {
+        let unorm = |v: u8| v as f32 / u8::MAX as f32;
+        let srgb = |v: u8| SRGB_TO_LINEAR[v as usize];
+        match self {
+            Self::Rgba8UnormSrgb => Vec4::new(
+                srgb(pixel[0]),
+                srgb(pixel[1]),
+                srgb(pixel[2]),
+                unorm(pixel[3]),
+            ),
+            Self::Bgra8UnormSrgb => Vec4::new(
+                srgb(pixel[2]),
+                srgb(pixel[1]),
+                srgb(pixel[0]),
+                unorm(pixel[3]),
+            ),
+            Self::Rgba8Unorm => {
+                Vec4::from_array([pixel[0], pixel[1], pixel[2], pixel[3]].map(unorm))
+            }
+            Self::R8Unorm => Vec4::new(unorm(pixel[0]), 0.0, 0.0, 1.0),
+            Self::Rgba16Float => Vec4::from_array(std::array::from_fn(|i| {
+                f16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]).to_f32()
+            })),
+            Self::Rgba32Float => Vec4::from_array(std::array::from_fn(|i| {
+                f32::from_le_bytes(pixel[i * 4..i * 4 + 4].try_into().unwrap())
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [PixelFormat; 6] = [
+        PixelFormat::Rgba8UnormSrgb,
+        PixelFormat::Bgra8UnormSrgb,
+        PixelFormat::Rgba8Unorm,
+        PixelFormat::R8Unorm,
+        PixelFormat::Rgba16Float,
+        PixelFormat::Rgba32Float,
+    ];
+
+    #[test]
+    fn texture_format_round_trip() {
+        for format in FORMATS {
+            assert_eq!(PixelFormat::new(format.texture_format()), Some(format));
+        }
+    }
+
+    #[test]
+    fn every_8_bit_value_round_trips() {
+        let formats = [
+            PixelFormat::Rgba8UnormSrgb,
+            PixelFormat::Bgra8UnormSrgb,
+            PixelFormat::Rgba8Unorm,
+        ];
+        for format in formats {
+            for value in 0..=u8::MAX {
+                let pixel = [
+                    value,
+                    value.wrapping_add(85),
+                    value.wrapping_add(170),
+                    value,
+                ];
+                let mut encoded = [0; 4];
+                format.encode(format.decode(&pixel), &mut encoded);
+                assert_eq!(encoded, pixel, "{format:?}");
+            }
+        }
+        for value in 0..=u8::MAX {
+            let mut encoded = [0];
+            PixelFormat::R8Unorm.encode(PixelFormat::R8Unorm.decode(&[value]), &mut encoded);
+            assert_eq!(encoded, [value]);
+        }
+    }
+
+    #[test]
+    fn decode_inverts_encode() {
+        // Whole steps of the 8 bit formats
+        let color = Vec4::new(0.0, 0.2, 0.6, 1.0);
+        for format in FORMATS {
+            let mut pixel = [0; MAX_PIXEL_SIZE];
+            format.encode(color, &mut pixel);
+            let decoded = format.decode(&pixel);
+            let expected = match format {
+                PixelFormat::R8Unorm => Vec4::new(color.x, 0.0, 0.0, 1.0),
+                _ => color,
+            };
+            // Half a step of 8 bit sRGB near 1, the least precise encoding
+            assert!(decoded.abs_diff_eq(expected, 0.005), "{format:?} {decoded}");
+        }
+    }
+
+    #[test]
+    fn hdr_formats_keep_bright_colors() {
+        let color = Vec4::new(4.0, 0.5, 100.0, 1.0);
+        for format in [PixelFormat::Rgba16Float, PixelFormat::Rgba32Float] {
+            let mut pixel = [0; MAX_PIXEL_SIZE];
+            format.encode(color, &mut pixel);
+            assert_eq!(format.decode(&pixel), color, "{format:?}");
+        }
+        let mut pixel = [0; 4];
+        PixelFormat::Rgba8Unorm.encode(color, &mut pixel);
+        assert_eq!(pixel, [255, 128, 255, 255]);
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use glam_wide::f32x8;
+
+use crate::{canvas::Vertex, varyings::Varyings};
+
+/// How vertex attributes are interpolated across a triangle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Perspective correct interpolation, the default.
+    #[default]
+    Perspective,
+    /// Linear interpolation in screen space. Attributes swim on triangles that aren't parallel to
+    /// the screen, which can be used for a retro look.
+    Affine,
+}
+
+/// Scalar or SIMD lane type the interpolation math is written against so the scalar and wide
+/// rasterizers share the same code.
+pub trait Lanes:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+{
+    fn splat(value: f32) -> Self;
+}
+
+impl Lanes for f32 {
+    #[inline(always)]
+    fn splat(value: f32) -> Self {
+        value
+    }
+}
+
+impl Lanes for f32x8 {
+    #[inline(always)]
+    fn splat(value: f32) -> Self {
+        f32x8::splat(value)
+    }
+}
+
+/// Turns the screen space barycentric weights of a triangle into the weights used to interpolate
+/// its attributes.
+///
+/// Depth is affine in screen space so it should always use the screen space weights.
+#[derive(Clone, Copy)]
+pub(crate) struct AttributeWeights<T> {
+    inv_w: [T; 3],
+    perspective: bool,
+}
+
+impl<T: Lanes> AttributeWeights<T> {
+    pub fn new(vertices: &[Vertex; 3], interpolation: Interpolation) -> Self {
+        let inv_w = vertices.map(|v| v.inv_w);
+        Self {
+            inv_w: inv_w.map(T::splat),
+            // When every vertex has the same w both modes give the same result
+            perspective: interpolation == Interpolation::Perspective
+                && !(inv_w[0] == inv_w[1] && inv_w[1] == inv_w[2]),
+        }
+    }
+
+    #[inline(always)]
+    pub fn apply(&self, weights: [T; 3]) -> [T; 3] {
+        if !self.perspective {
+            return weights;
+        }
+        let [a, b, c] = weights;
+        let a = a * self.inv_w[0];
+        let b = b * self.inv_w[1];
+        let c = c * self.inv_w[2];
+        let sum = a + b + c;
+        [a / sum, b / sum, c / sum]
+    }
+}
+
+/// Per triangle data needed to compute the depth and varyings of its fragments
+pub(crate) struct TriangleAttributes<'t, T> {
+    depth: [T; 3],
+    weights: AttributeWeights<T>,
+    varyings: [&'t Varyings; 3],
+}
+
+impl<'t, T: Lanes> TriangleAttributes<'t, T> {
+    pub fn new(vertices: &'t [Vertex; 3], interpolation: Interpolation) -> Self {
+        Self {
+            depth: vertices.map(|v| T::splat(v.pos.z)),
+            weights: AttributeWeights::new(vertices, interpolation),
+            varyings: [0, 1, 2].map(|i| &vertices[i].varyings),
+        }
+    }
+
+    /// Interpolates the depth using the screen space weights
+    #[inline(always)]
+    pub fn depth(&self, weights: [T; 3]) -> T {
+        self.depth[0] * weights[0] + self.depth[1] * weights[1] + self.depth[2] * weights[2]
+    }
+
+    /// Interpolates the varyings using the screen space weights
+    #[inline(always)]
+    pub fn varyings(&self, weights: [T; 3]) -> Varyings<T> {
+        Varyings::interpolate(self.varyings, self.weights.apply(weights))
+    }
+}
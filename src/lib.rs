@@ -1,23 +1,65 @@
 use bevy::{
     asset::RenderAssetUsages,
+    camera::Viewport,
     ecs::system::SystemParam,
-    image::TextureFormatPixelInfo,
     prelude::*,
     render::{extract_component::ExtractComponent, renderer::RenderDevice},
     window::WindowResolution,
 };
 use wgpu::{Extent3d, TextureDimension, TextureFormat};
 
-use crate::canvas::GlaciersCanvas;
+use crate::{
+    canvas::{GlaciersCanvas, Primitive},
+    depth::DepthBuffer,
+    format::PixelFormat,
+    msaa::{SampleBuffer, SampleData, sample_offsets},
+    rasterizer::RasterizerStrategy,
+    tonemapping::TonemapOperator,
+};
 
+mod batch;
+pub mod blend;
 pub mod canvas;
+pub mod clip;
+pub mod depth;
+pub mod format;
+pub mod interpolation;
+pub mod line;
+mod mesh;
+pub mod msaa;
 pub mod plugin;
+pub mod rasterizer;
+pub mod shader;
+pub mod texture;
+pub mod tiles;
+pub mod tonemapping;
+pub mod varyings;
 
 #[derive(Component, Default, Clone, ExtractComponent)]
 pub struct GlaciersContext {
     pub image: Handle<Image>,
+    pub depth: Handle<DepthBuffer>,
+    /// Only set when multisampling or high dynamic range is enabled
+    pub samples: Option<Handle<SampleBuffer>>,
+    pub msaa: Msaa,
+    /// Maps the high dynamic range samples to the displayed image when the canvas is resolved.
+    /// Unused when the context isn't high dynamic range.
+    pub tonemapping: TonemapOperator,
+    /// Exposure in stops applied before the tonemapping, each stop doubles the brightness
+    pub exposure: f32,
     pub scale: f32,
     pub image_size: UVec2,
+    /// Rasterizer used by [`GlaciersCanvas::draw_triangle`] and [`GlaciersCanvas::draw_mesh`]
+    pub rasterizer: RasterizerStrategy,
+    /// Panic with the primitive being drawn when a pixel outside of the canvas or the scissor
+    /// rectangle is written instead of silently dropping it, only in debug builds.
+    ///
+    /// Triangles and lines are clipped to the scissor rectangle by the rasterizers, so it catches
+    /// points drawn outside of it and rasterizer bugs.
+    pub assert_bounds: bool,
+    /// Outline the blocks visited by the box rasterizers, in green when something was drawn in
+    /// them and in red otherwise
+    pub show_box_outlines: bool,
 }
 
 impl GlaciersContext {
@@ -30,16 +72,35 @@ impl GlaciersContext {
 #[derive(SystemParam)]
 pub struct GlaciersParams<'w, 's> {
     images: ResMut<'w, Assets<Image>>,
+    depth_buffers: ResMut<'w, Assets<DepthBuffer>>,
+    sample_buffers: ResMut<'w, Assets<SampleBuffer>>,
     context: Query<'w, 's, &'static GlaciersContext>,
     _render_device: Res<'w, RenderDevice>,
 }
 
 impl<'w, 's> GlaciersParams<'w, 's> {
+    /// Creates the canvas image and its buffers. Falls back to [`TextureFormat::Rgba8UnormSrgb`]
+    /// if the canvas can't draw to `format`, see [`PixelFormat::new`].
+    ///
+    /// Float formats create a high dynamic range context: everything is drawn to a sample buffer
+    /// in that format, which is tonemapped into an `Rgba8UnormSrgb` image when the canvas is
+    /// resolved, see [`GlaciersContext::tonemapping`].
     pub fn init_context<'a>(
         &'a mut self,
         resolution: WindowResolution,
         scale: f32,
+        msaa: Msaa,
+        format: TextureFormat,
     ) -> GlaciersContext {
+        let format = PixelFormat::new(format).unwrap_or_else(|| {
+            warn!("Unsupported canvas format {format:?}, using Rgba8UnormSrgb instead");
+            PixelFormat::Rgba8UnormSrgb
+        });
+        let (image_format, sample_format) = if format.is_hdr() {
+            (PixelFormat::Rgba8UnormSrgb, Some(format))
+        } else {
+            (format, (msaa != Msaa::Off).then_some(format))
+        };
         let image_size =
             Vec2::new(resolution.width() * scale, resolution.height() * scale).as_uvec2();
         let image = Image::new_fill(
@@ -49,12 +110,18 @@ impl<'w, 's> GlaciersParams<'w, 's> {
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
-            &[0u8; 4],
-            TextureFormat::Rgba8UnormSrgb,
+            &vec![0u8; image_format.size()],
+            image_format.texture_format(),
             RenderAssetUsages::all(),
         );
         GlaciersContext {
             image: self.images.add(image),
+            depth: self.depth_buffers.add(DepthBuffer::new(image_size, msaa)),
+            samples: sample_format.map(|format| {
+                self.sample_buffers
+                    .add(SampleBuffer::new(image_size, msaa, format))
+            }),
+            msaa,
             scale,
             image_size,
             ..default()
@@ -68,10 +135,50 @@ impl<'w, 's> GlaciersParams<'w, 's> {
     pub fn canvas<'a>(&'a mut self) -> GlaciersCanvas<'a> {
         let context = self.context.single().unwrap();
         let image = self.images.get_mut(context.image.id()).unwrap();
-        let pixel_size = image.texture_descriptor.format.pixel_size().unwrap();
+        // The image is created by init_context with a supported format
+        let format = PixelFormat::new(image.texture_descriptor.format).unwrap();
+        let size = image.size();
+        let color = image
+            .data
+            .get_or_insert_with(|| vec![0; (size.x * size.y) as usize * format.size()]);
+        let DepthBuffer {
+            samples: SampleData {
+                data: depth, msaa, ..
+            },
+            clear_value: depth_clear_value,
+        } = self.depth_buffers.get_mut(context.depth.id()).unwrap();
+        let (samples, sample_format) = match context
+            .samples
+            .as_ref()
+            .and_then(|samples| self.sample_buffers.get_mut(samples.id()))
+        {
+            Some(buffer) => (Some(&mut buffer.samples.data[..]), buffer.format),
+            None => (None, format),
+        };
         GlaciersCanvas {
-            color: image,
-            pixel_size,
+            color,
+            depth,
+            samples,
+            size,
+            rows: 0..size.y,
+            msaa: *msaa,
+            depth_clear_value: *depth_clear_value,
+            sample_offsets: sample_offsets(context.msaa),
+            format,
+            sample_format,
+            tonemapping: context.tonemapping,
+            exposure: context.exposure,
+            viewport: Viewport {
+                physical_position: UVec2::ZERO,
+                physical_size: size,
+                depth: 0.0..1.0,
+            },
+            scissor: URect::from_corners(UVec2::ZERO, size),
+            rasterizer: context.rasterizer,
+            assert_bounds: context.assert_bounds,
+            show_box_outlines: context.show_box_outlines,
+            primitive: Primitive::None,
+            resolve_on_drop: true,
         }
     }
 
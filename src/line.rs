@@ -0,0 +1,211 @@
+//! Anti-aliased lines with a width and caps, see [`GlaciersCanvas::draw_line_aa`].
+
+use std::f32::consts::SQRT_2;
+
+use bevy::prelude::*;
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+use crate::{
+    canvas::{DrawState, GlaciersCanvas, Primitive, Triangle},
+    clip::clip_line,
+};
+
+/// Replaces the canvas color proportionally to the coverage stored in the blend constant, used
+/// to anti-alias lines drawn without blending.
+const COVERAGE_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Constant,
+        dst_factor: BlendFactor::OneMinusConstant,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::Constant,
+        dst_factor: BlendFactor::OneMinusConstant,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// Shape of the ends of a line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line stops at its endpoints
+    #[default]
+    Butt,
+    /// The line is extended past its endpoints by half its width
+    Square,
+    /// The line ends with half circles centered on its endpoints
+    Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    /// Width of the line in pixels
+    pub width: f32,
+    pub cap: LineCap,
+}
+
+impl LineStyle {
+    pub const DEFAULT: Self = Self {
+        width: 1.0,
+        cap: LineCap::Butt,
+    };
+
+    pub const fn new(width: f32, cap: LineCap) -> Self {
+        Self { width, cap }
+    }
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl GlaciersCanvas<'_> {
+    /// Draws an anti-aliased line between two points in canvas space. Unlike
+    /// [`GlaciersCanvas::draw_line`] the endpoints aren't snapped to pixels, the centers of the
+    /// pixels are at `.5`.
+    ///
+    /// Each pixel is covered proportionally to its distance to the line. Without a blend state in
+    /// `state` the coverage fades between the canvas and `color`, otherwise the alpha of `color`
+    /// is multiplied by the coverage before blending.
+    ///
+    /// Depth is interpolated along the line and tested once per pixel.
+    pub fn draw_line_aa(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        color: Color,
+        style: &LineStyle,
+        state: &DrawState,
+    ) {
+        self.draw_line_aa_gradient(start, end, [color; 2], style, state);
+    }
+
+    /// Anti-aliased version of [`GlaciersCanvas::draw_line_gradient`]
+    pub fn draw_line_aa_gradient(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        colors: [Color; 2],
+        style: &LineStyle,
+        state: &DrawState,
+    ) {
+        self.rasterize_line_aa(start, end, colors, style, 0.0, state);
+    }
+
+    /// See [`GlaciersCanvas::draw_line_aa`], `depth_slope` is used by the depth bias
+    fn rasterize_line_aa(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        colors: [Color; 2],
+        style: &LineStyle,
+        depth_slope: f32,
+        state: &DrawState,
+    ) {
+        let half_width = style.width.max(0.0) * 0.5;
+        // The margin covers the corners of square caps and the anti-aliased fringe
+        let margin = (half_width + 1.0) * SQRT_2;
+
+        self.begin_primitive(Primitive::Line(start, end));
+        // The clipped ends are at least `margin` away from the canvas so their caps aren't visible
+        let Some((t0, t1)) = clip_line(start.xy(), end.xy(), self.bounds().inflate(margin)) else {
+            return;
+        };
+        let [c0, c1] = colors.map(|color| color.to_linear().to_vec4());
+        let colors = [c0.lerp(c1, t0), c0.lerp(c1, t1)];
+        let (start, end) = (start.lerp(end, t0), start.lerp(end, t1));
+
+        let delta = end.xy() - start.xy();
+        let length = delta.length();
+        let dir = delta.try_normalize().unwrap_or(Vec2::X);
+        let normal = dir.perp();
+        // How far the shape goes past the endpoints along the line
+        let extension = match style.cap {
+            LineCap::Butt => 0.0,
+            LineCap::Square | LineCap::Round => half_width,
+        };
+
+        // Walk the major axis and only visit the pixels of the minor axis close to the line
+        let x_major = delta.x.abs() >= delta.y.abs();
+        let axes = |v: Vec2| if x_major { v } else { v.yx() };
+        let bounds = self.bounds();
+        let (min, max) = (axes(bounds.min), axes(bounds.max));
+        let (origin, major_delta) = (axes(start.xy()), axes(delta));
+        let slope = if major_delta.x == 0.0 {
+            0.0
+        } else {
+            major_delta.y / major_delta.x
+        };
+        let major_min = (origin.x.min(origin.x + major_delta.x) - margin).max(min.x);
+        let major_max = (origin.x.max(origin.x + major_delta.x) + margin).min(max.x);
+
+        for major in major_min as i32..major_max.ceil() as i32 {
+            let center = origin.y + (major as f32 + 0.5 - origin.x) * slope;
+            let minor_min = (center - margin).max(min.y);
+            let minor_max = (center + margin).min(max.y);
+            for minor in minor_min as i32..minor_max.ceil() as i32 {
+                let pos = axes(Vec2::new(major as f32, minor as f32)).as_ivec2();
+                let offset = pos.as_vec2() + 0.5 - start.xy();
+                let along = offset.dot(dir);
+                // Signed distance from the pixel center to the edge of the line
+                let distance = match style.cap {
+                    LineCap::Round => {
+                        (offset - dir * along.clamp(0.0, length)).length() - half_width
+                    }
+                    LineCap::Butt | LineCap::Square => (offset.dot(normal).abs() - half_width)
+                        .max(-extension - along)
+                        .max(along - length - extension),
+                };
+                let coverage = (0.5 - distance).clamp(0.0, 1.0);
+                if coverage == 0.0 {
+                    continue;
+                }
+
+                let t = if length == 0.0 {
+                    0.0
+                } else {
+                    (along / length).clamp(0.0, 1.0)
+                };
+                let depth = state.depth.biased(start.z.lerp(end.z, t), depth_slope);
+                let samples =
+                    self.depth_test(pos, self.all_samples(), depth, Vec2::ZERO, &state.depth);
+                if samples == 0 {
+                    continue;
+                }
+                self.write_depth(pos, samples, depth, Vec2::ZERO, &state.depth);
+                let color = colors[0].lerp(colors[1], t);
+                match state.blend {
+                    Some(_) => {
+                        let color = color.with_w(color.w * coverage);
+                        self.write_color(pos, samples, color, state);
+                    }
+                    None => {
+                        let state = DrawState {
+                            blend: Some(COVERAGE_BLENDING),
+                            blend_constant: Vec4::splat(coverage),
+                            ..*state
+                        };
+                        self.write_color(pos, samples, color, &state);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Anti-aliased version of [`GlaciersCanvas::draw_triangle_wireframe`]
+    pub fn draw_triangle_wireframe_aa(
+        &mut self,
+        triangle: &Triangle,
+        color: Color,
+        style: &LineStyle,
+        state: &DrawState,
+    ) {
+        let slope = triangle.depth_slope();
+        let [a, b, c] = triangle.vertices.map(|v| v.pos);
+        self.rasterize_line_aa(a, b, [color; 2], style, slope, state);
+        self.rasterize_line_aa(b, c, [color; 2], style, slope, state);
+        self.rasterize_line_aa(c, a, [color; 2], style, slope, state);
+    }
+}
@@ -0,0 +1,118 @@
+use bevy::{mesh::PrimitiveTopology, prelude::*};
+
+use crate::{
+    canvas::{DrawState, GlaciersCanvas, Triangle},
+    clip::{ClipVertex, DEFAULT_GUARD_BAND, clip_triangle},
+    line::LineStyle,
+    rasterizer::RasterizerStrategy,
+    shader::{FragmentShader, MeshVertex, VertexShader},
+};
+
+impl GlaciersCanvas<'_> {
+    /// Draws a mesh the same way a gpu draw call would.
+    ///
+    /// Every vertex goes through the `vertex_shader`, the triangles are assembled from the
+    /// indices, clipped, mapped to the canvas and rasterized using the `fragment_shader`.
+    ///
+    /// Only triangle lists and triangle strips are supported. With [`RasterizerStrategy::Auto`]
+    /// the triangles are rasterized in parallel, see [`GlaciersCanvas::draw_triangles_parallel`],
+    /// otherwise one by one by the chosen rasterizer, see [`GlaciersCanvas::draw_triangle`].
+    pub fn draw_mesh<V: VertexShader>(
+        &mut self,
+        mesh: &Mesh,
+        uniforms: &V::Uniforms,
+        state: &DrawState,
+        vertex_shader: &V,
+        fragment_shader: &(impl FragmentShader + Sync),
+    ) {
+        let _draw_mesh_span = info_span!("draw_mesh").entered();
+        if self.rasterizer != RasterizerStrategy::Auto {
+            self.for_each_mesh_triangle(mesh, uniforms, vertex_shader, |canvas, triangle| {
+                canvas.draw_triangle(triangle, state, fragment_shader);
+            });
+            return;
+        }
+        let mut triangles = Vec::new();
+        self.for_each_mesh_triangle(mesh, uniforms, vertex_shader, |_, triangle| {
+            triangles.push(*triangle);
+        });
+        self.draw_triangles_parallel(&triangles, state, fragment_shader);
+    }
+
+    /// Draws the edges of every triangle of a mesh with anti-aliased lines after running the
+    /// vertex stage, see [`GlaciersCanvas::draw_mesh`].
+    pub fn draw_mesh_wireframe<V: VertexShader>(
+        &mut self,
+        mesh: &Mesh,
+        uniforms: &V::Uniforms,
+        color: Color,
+        style: &LineStyle,
+        state: &DrawState,
+        vertex_shader: &V,
+    ) {
+        self.for_each_mesh_triangle(mesh, uniforms, vertex_shader, |canvas, triangle| {
+            canvas.draw_triangle_wireframe_aa(triangle, color, style, state);
+        });
+    }
+
+    /// Runs the vertex stage, primitive assembly, clipping and viewport transform
+    fn for_each_mesh_triangle<V: VertexShader>(
+        &mut self,
+        mesh: &Mesh,
+        uniforms: &V::Uniforms,
+        vertex_shader: &V,
+        mut f: impl FnMut(&mut Self, &Triangle),
+    ) {
+        if mesh.attribute(Mesh::ATTRIBUTE_POSITION).is_none() {
+            warn!("Missing vertex attribute position");
+            return;
+        }
+        let topology = mesh.primitive_topology();
+        if !matches!(
+            topology,
+            PrimitiveTopology::TriangleList | PrimitiveTopology::TriangleStrip
+        ) {
+            warn!("Unsupported primitive topology {topology:?}");
+            return;
+        }
+
+        let vertices: Vec<ClipVertex> = (0..mesh.count_vertices())
+            .map(|index| vertex_shader.shade(&MeshVertex { mesh, index }, uniforms))
+            .collect();
+
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..vertices.len()).collect(),
+        };
+
+        let viewport = self.viewport().clone();
+        let mut assemble = |canvas: &mut Self, triangle: [usize; 3]| {
+            // Triangles using out of bounds vertices are discarded
+            let [Some(a), Some(b), Some(c)] = triangle.map(|i| vertices.get(i).copied()) else {
+                return;
+            };
+            for triangle in clip_triangle(&[a, b, c], &viewport, DEFAULT_GUARD_BAND) {
+                f(canvas, &triangle);
+            }
+        };
+
+        match topology {
+            PrimitiveTopology::TriangleList => {
+                for triangle in indices.chunks_exact(3) {
+                    assemble(self, [triangle[0], triangle[1], triangle[2]]);
+                }
+            }
+            PrimitiveTopology::TriangleStrip => {
+                for (i, triangle) in indices.windows(3).enumerate() {
+                    // Every other triangle of a strip is flipped to keep the same winding
+                    if i % 2 == 0 {
+                        assemble(self, [triangle[0], triangle[1], triangle[2]]);
+                    } else {
+                        assemble(self, [triangle[1], triangle[0], triangle[2]]);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
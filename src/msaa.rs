@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+
+use crate::format::PixelFormat;
+
+/// Maximum number of samples per pixel, see [`Msaa::Sample8`]
+pub const MAX_SAMPLES: usize = 8;
+
+/// Standard sample positions used by d3d, vulkan and metal, in 1/16th of a pixel from the top
+/// left corner of the pixel.
+const SAMPLE_POSITIONS_1: [IVec2; 1] = [IVec2::new(8, 8)];
+const SAMPLE_POSITIONS_2: [IVec2; 2] = [IVec2::new(12, 12), IVec2::new(4, 4)];
+const SAMPLE_POSITIONS_4: [IVec2; 4] = [
+    IVec2::new(6, 2),
+    IVec2::new(14, 6),
+    IVec2::new(2, 10),
+    IVec2::new(10, 14),
+];
+const SAMPLE_POSITIONS_8: [IVec2; 8] = [
+    IVec2::new(9, 5),
+    IVec2::new(7, 11),
+    IVec2::new(13, 9),
+    IVec2::new(5, 3),
+    IVec2::new(3, 13),
+    IVec2::new(1, 7),
+    IVec2::new(11, 15),
+    IVec2::new(15, 1),
+];
+
+/// Returns the sample positions of a pixel in 1/16th of a pixel from its top left corner
+pub fn sample_positions(msaa: Msaa) -> &'static [IVec2] {
+    match msaa {
+        Msaa::Off => &SAMPLE_POSITIONS_1,
+        Msaa::Sample2 => &SAMPLE_POSITIONS_2,
+        Msaa::Sample4 => &SAMPLE_POSITIONS_4,
+        Msaa::Sample8 => &SAMPLE_POSITIONS_8,
+    }
+}
+
+/// Returns the sample positions of a pixel relative to its center, in pixels
+pub(crate) fn sample_offsets(msaa: Msaa) -> [Vec2; MAX_SAMPLES] {
+    let mut offsets = [Vec2::ZERO; MAX_SAMPLES];
+    for (offset, position) in offsets.iter_mut().zip(sample_positions(msaa)) {
+        *offset = position.as_vec2() / 16.0 - 0.5;
+    }
+    offsets
+}
+
+/// Values stored for every sample of every pixel, shared by the
+/// [`DepthBuffer`](crate::depth::DepthBuffer) and the [`SampleBuffer`].
+#[derive(Debug, Clone)]
+pub(crate) struct SampleData<T> {
+    pub(crate) size: UVec2,
+    pub(crate) msaa: Msaa,
+    /// Number of values of each sample
+    stride: usize,
+    /// The samples of a pixel are next to each other
+    pub(crate) data: Vec<T>,
+}
+
+impl<T: Copy> SampleData<T> {
+    pub(crate) fn new(size: UVec2, msaa: Msaa, stride: usize, value: T) -> Self {
+        let mut samples = Self {
+            size,
+            msaa,
+            stride,
+            data: Vec::new(),
+        };
+        samples.resize(size, value);
+        samples
+    }
+
+    /// Resizes the buffer and fills it with `value`, the content can't be meaningfully
+    /// preserved.
+    pub(crate) fn resize(&mut self, size: UVec2, value: T) {
+        self.size = size;
+        self.data.clear();
+        let len = (size.x * size.y * self.msaa.samples()) as usize * self.stride;
+        self.data.resize(len, value);
+    }
+
+    /// Returns the values of a sample, `None` if it's outside of the buffer
+    pub(crate) fn get(&self, pos: UVec2, sample: usize) -> Option<&[T]> {
+        if pos.x >= self.size.x || pos.y >= self.size.y || sample >= self.msaa.samples() as usize {
+            return None;
+        }
+        let pixel = (pos.y * self.size.x + pos.x) as usize * self.msaa.samples() as usize;
+        let index = (pixel + sample) * self.stride;
+        Some(&self.data[index..index + self.stride])
+    }
+}
+
+/// Multisampled or high dynamic range color attachment of a
+/// [`GlaciersCanvas`](crate::canvas::GlaciersCanvas).
+///
+/// Triangles write the samples they cover here and [`GlaciersCanvas::resolve`] averages them
+/// into the canvas image, tonemapping them if they are in a high dynamic range format. Like the
+/// [`DepthBuffer`](crate::depth::DepthBuffer) it never needs to be uploaded to the gpu.
+///
+/// [`GlaciersCanvas::resolve`]: crate::canvas::GlaciersCanvas::resolve
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct SampleBuffer {
+    /// Usually the format of the canvas so samples are as precise as its pixels, or a float
+    /// format for high dynamic range
+    pub(crate) format: PixelFormat,
+    pub(crate) samples: SampleData<u8>,
+}
+
+impl SampleBuffer {
+    pub fn new(size: UVec2, msaa: Msaa, format: PixelFormat) -> Self {
+        Self {
+            format,
+            samples: SampleData::new(size, msaa, format.size(), 0),
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.samples.size
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        self.samples.msaa
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Resizes the buffer. The content is reset since it can't be meaningfully preserved.
+    pub fn resize(&mut self, size: UVec2) {
+        self.samples.resize(size, 0);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.data.fill(0);
+    }
+}
@@ -16,14 +16,19 @@ use bevy::{
     ui_render::graph::NodeUi,
     window::WindowResized,
 };
-use wgpu::{Extent3d, TextureFormat, util::TextureBlitter};
+use wgpu::{
+    BindGroupLayout, CommandEncoder, Device, Extent3d, RenderPipeline, Sampler, TextureFormat,
+    TextureView,
+};
 
-use crate::GlaciersContext;
+use crate::{GlaciersContext, depth::DepthBuffer, msaa::SampleBuffer};
 
 pub struct GlaciersPlugin;
 impl Plugin for GlaciersPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractComponentPlugin::<GlaciersContext>::default())
+        app.init_asset::<DepthBuffer>()
+            .init_asset::<SampleBuffer>()
+            .add_plugins(ExtractComponentPlugin::<GlaciersContext>::default())
             .add_systems(PreUpdate, handle_resize);
     }
 
@@ -33,7 +38,7 @@ impl Plugin for GlaciersPlugin {
         };
         // TODO consider using a custom graph on the camera
         render_app
-            .init_resource::<TextureBlitterCache>()
+            .init_resource::<CanvasBlitterCache>()
             .add_render_graph_node::<ViewNodeRunner<GlaciersNode>>(Core3d, GlaciersLabel)
             .add_render_graph_edges(
                 Core3d,
@@ -43,13 +48,15 @@ impl Plugin for GlaciersPlugin {
                     NodeUi::UiPass,
                 ),
             )
-            .add_systems(Render, prepare_texture_blitter.after(prepare_view_targets));
+            .add_systems(Render, prepare_canvas_blitter.after(prepare_view_targets));
     }
 }
 
 fn handle_resize(
     mut ctx: Query<&GlaciersContext>,
     mut images: ResMut<Assets<Image>>,
+    mut depth_buffers: ResMut<Assets<DepthBuffer>>,
+    mut sample_buffers: ResMut<Assets<SampleBuffer>>,
     mut resize_events: MessageReader<WindowResized>,
 ) {
     // TODO handle multiple contexts
@@ -69,11 +76,25 @@ fn handle_resize(
             continue;
         }
 
+        let size = UVec2::new(
+            (ev.width * ctx.scale) as u32,
+            (ev.height * ctx.scale) as u32,
+        );
         image.resize(Extent3d {
-            width: (ev.width * ctx.scale) as u32,
-            height: (ev.height * ctx.scale) as u32,
+            width: size.x,
+            height: size.y,
             depth_or_array_layers: 1,
         });
+        if let Some(depth) = depth_buffers.get_mut(ctx.depth.id()) {
+            depth.resize(size);
+        }
+        if let Some(samples) = ctx
+            .samples
+            .as_ref()
+            .and_then(|samples| sample_buffers.get_mut(samples.id()))
+        {
+            samples.resize(size);
+        }
         println!("Image size: {} ", image.size());
     }
 }
@@ -98,16 +119,16 @@ impl ViewNode for GlaciersNode {
             return Ok(());
         };
         let view_entity = _graph.view_entity();
-        let Some((_, texture_blitter)) = world.resource::<TextureBlitterCache>().get(&view_entity)
-        else {
+        let Some((_, blitter)) = world.resource::<CanvasBlitterCache>().get(&view_entity) else {
             return Ok(());
         };
 
-        texture_blitter.copy(
+        blitter.copy(
             world.resource::<RenderDevice>().wgpu_device(),
             render_context.command_encoder(),
             &image.texture_view,
-            &view_target.main_texture_view(),
+            image.texture_format,
+            view_target.main_texture_view(),
         );
 
         Ok(())
@@ -115,24 +136,145 @@ impl ViewNode for GlaciersNode {
 }
 
 #[derive(Resource, Deref, DerefMut, Default)]
-pub struct TextureBlitterCache(EntityHashMap<(TextureFormat, TextureBlitter)>);
+pub struct CanvasBlitterCache(EntityHashMap<(TextureFormat, CanvasBlitter)>);
 
-fn prepare_texture_blitter(
+fn prepare_canvas_blitter(
     render_device: Res<RenderDevice>,
     views: Query<(Entity, &ViewTarget), Changed<ViewTarget>>,
-    mut blitter_cache: ResMut<TextureBlitterCache>,
+    mut blitter_cache: ResMut<CanvasBlitterCache>,
 ) {
     for (e, view_target) in &views {
         // TODO make the format part of the key
         let (format, _) = blitter_cache.entry(e).or_insert_with(|| {
-            let texture_blitter = wgpu::util::TextureBlitter::new(
+            let blitter = CanvasBlitter::new(
                 render_device.wgpu_device(),
                 view_target.main_texture_format(),
             );
-            (view_target.main_texture_format(), texture_blitter)
+            (view_target.main_texture_format(), blitter)
         });
         if *format != view_target.main_texture_format() {
             unimplemented!("ViewTarget format changed");
         }
     }
 }
+
+/// Copies the canvas image to a view, like [`wgpu::util::TextureBlitter`] except that
+/// [`TextureFormat::R8Unorm`] canvases are shown in grey instead of red.
+pub struct CanvasBlitter {
+    pipeline: RenderPipeline,
+    /// Used for single channel canvases
+    grey_pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl CanvasBlitter {
+    /// `format` is the format of the views the canvas is copied to
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glaciers_blit_sampler"),
+            ..default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("glaciers_blit_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("glaciers_blit_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+        let pipeline = |fragment_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("glaciers_blit_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: default(),
+                    buffers: &[],
+                },
+                primitive: default(),
+                depth_stencil: None,
+                multisample: default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment_entry_point),
+                    compilation_options: default(),
+                    targets: &[Some(format.into())],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+        Self {
+            pipeline: pipeline("fs_main"),
+            grey_pipeline: pipeline("fs_grey"),
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Copies `source`, a canvas image in `source_format`, to `target`
+    pub fn copy(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source: &TextureView,
+        source_format: TextureFormat,
+        target: &TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("glaciers_blit_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("glaciers_blit_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..default()
+        });
+        let pipeline = match source_format {
+            TextureFormat::R8Unorm => &self.grey_pipeline,
+            _ => &self.pipeline,
+        };
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,56 @@
+//! Selection of the rasterizer used by
+//! [`GlaciersCanvas::draw_triangle`](crate::canvas::GlaciersCanvas::draw_triangle), see
+//! [`RasterizerStrategy`].
+
+use bevy::prelude::*;
+
+/// Bounding boxes larger than this, in pixels, are drawn by [`RasterizerStrategy::WideBox`] with
+/// [`RasterizerStrategy::Auto`] when they're roughly square, see [`BOX_ORIENT`]
+const LARGE_AREA: f32 = 64.0 * 64.0;
+
+/// Range of width to height ratios of the bounding box the box rasterizers walk in blocks.
+/// Thinner triangles cover most of their bounding box so it's scanned entirely instead.
+pub(crate) const BOX_ORIENT: std::ops::RangeInclusive<f32> = 0.4..=1.6;
+
+/// Rasterizer used by
+/// [`GlaciersCanvas::draw_triangle`](crate::canvas::GlaciersCanvas::draw_triangle) and
+/// [`GlaciersCanvas::draw_mesh`](crate::canvas::GlaciersCanvas::draw_mesh), set with
+/// [`GlaciersContext::rasterizer`](crate::GlaciersContext::rasterizer).
+///
+/// All the rasterizers snap vertices to the same sub pixel grid, sample the same positions and
+/// follow the top-left fill rule, so they draw the same pixels and only differ in speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RasterizerStrategy {
+    /// Picks between [`RasterizerStrategy::Wide`] and [`RasterizerStrategy::WideBox`] for each
+    /// triangle from the size and the aspect ratio of its bounding box
+    #[default]
+    Auto,
+    /// Shades one quad of 2x2 pixels at a time, see
+    /// [`draw_triangle_scalar`](crate::canvas::GlaciersCanvas::draw_triangle_scalar)
+    Scalar,
+    /// Like [`RasterizerStrategy::Scalar`] but skips the empty blocks of the bounding box, see
+    /// [`draw_triangle_box`](crate::canvas::GlaciersCanvas::draw_triangle_box)
+    ScalarBox,
+    /// Shades 2 quads at a time with SIMD, see
+    /// [`draw_triangle_wide`](crate::canvas::GlaciersCanvas::draw_triangle_wide)
+    Wide,
+    /// Like [`RasterizerStrategy::Wide`] but skips the empty blocks of the bounding box, see
+    /// [`draw_triangle_wide_box`](crate::canvas::GlaciersCanvas::draw_triangle_wide_box)
+    WideBox,
+}
+
+impl RasterizerStrategy {
+    /// Resolves [`RasterizerStrategy::Auto`] for a triangle with the bounding box `(min, max)`,
+    /// other strategies are returned as is.
+    pub fn select(self, (min, max): (Vec3, Vec3)) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let size = (max - min).xy();
+        if size.x * size.y > LARGE_AREA && BOX_ORIENT.contains(&(size.x / size.y)) {
+            Self::WideBox
+        } else {
+            Self::Wide
+        }
+    }
+}
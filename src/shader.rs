@@ -0,0 +1,222 @@
+use bevy::{
+    mesh::{MeshVertexAttributeId, VertexAttributeValues},
+    prelude::*,
+};
+use glam_wide::{Vec2x8, Vec3x8, f32x8};
+
+use crate::{
+    clip::ClipVertex,
+    varyings::{Varyings, VaryingsX8},
+};
+
+/// A vertex of a [`Mesh`] handed to the [`VertexShader`]
+#[derive(Clone, Copy)]
+pub struct MeshVertex<'m> {
+    pub mesh: &'m Mesh,
+    /// Index of the vertex in the vertex attributes
+    pub index: usize,
+}
+
+impl MeshVertex<'_> {
+    /// Returns the [`Mesh::ATTRIBUTE_POSITION`] of the vertex.
+    ///
+    /// [`GlaciersCanvas::draw_mesh`](crate::canvas::GlaciersCanvas::draw_mesh) skips meshes
+    /// without positions so this is always valid in a vertex shader.
+    pub fn position(&self) -> Vec3 {
+        self.vec3(Mesh::ATTRIBUTE_POSITION).unwrap_or_default()
+    }
+
+    pub fn normal(&self) -> Option<Vec3> {
+        self.vec3(Mesh::ATTRIBUTE_NORMAL)
+    }
+
+    pub fn uv(&self) -> Option<Vec2> {
+        self.vec2(Mesh::ATTRIBUTE_UV_0)
+    }
+
+    /// Returns the [`Mesh::ATTRIBUTE_COLOR`] of the vertex, which is linear rgba
+    pub fn color(&self) -> Option<Vec4> {
+        self.vec4(Mesh::ATTRIBUTE_COLOR)
+    }
+
+    /// Returns `None` if the mesh doesn't have the attribute or if it isn't a `Float32x2`
+    pub fn vec2(&self, attribute: impl Into<MeshVertexAttributeId>) -> Option<Vec2> {
+        match self.mesh.attribute(attribute)? {
+            VertexAttributeValues::Float32x2(values) => values.get(self.index).map(|&v| v.into()),
+            _ => None,
+        }
+    }
+
+    /// Returns `None` if the mesh doesn't have the attribute or if it isn't a `Float32x3`
+    pub fn vec3(&self, attribute: impl Into<MeshVertexAttributeId>) -> Option<Vec3> {
+        match self.mesh.attribute(attribute)? {
+            VertexAttributeValues::Float32x3(values) => values.get(self.index).map(|&v| v.into()),
+            _ => None,
+        }
+    }
+
+    /// Returns `None` if the mesh doesn't have the attribute or if it isn't a `Float32x4`
+    pub fn vec4(&self, attribute: impl Into<MeshVertexAttributeId>) -> Option<Vec4> {
+        match self.mesh.attribute(attribute)? {
+            VertexAttributeValues::Float32x4(values) => values.get(self.index).map(|&v| v.into()),
+            _ => None,
+        }
+    }
+}
+
+/// Uniforms used by [`MeshVertexShader`], and a good starting point for custom vertex shaders
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshUniforms {
+    pub clip_from_world: Mat4,
+    pub world_from_local: Mat4,
+}
+
+impl MeshUniforms {
+    pub fn new(
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        transform: &GlobalTransform,
+    ) -> Self {
+        Self {
+            clip_from_world: camera.clip_from_view() * camera_transform.to_matrix().inverse(),
+            world_from_local: transform.to_matrix(),
+        }
+    }
+}
+
+/// Programmable vertex stage of
+/// [`GlaciersCanvas::draw_mesh`](crate::canvas::GlaciersCanvas::draw_mesh)
+pub trait VertexShader {
+    /// Data shared by every vertex of a draw call, like the camera and model matrices
+    type Uniforms;
+
+    /// Returns the clip space position of the vertex and the varyings handed to the fragment
+    /// stage
+    fn shade(&self, vertex: &MeshVertex, uniforms: &Self::Uniforms) -> ClipVertex;
+}
+
+/// Transforms the vertices to clip space and outputs the vertex color, or white when the mesh
+/// doesn't have colors. Use it with [`VertexColor`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeshVertexShader;
+
+impl VertexShader for MeshVertexShader {
+    type Uniforms = MeshUniforms;
+
+    fn shade(&self, vertex: &MeshVertex, uniforms: &MeshUniforms) -> ClipVertex {
+        let clip_from_local = uniforms.clip_from_world * uniforms.world_from_local;
+        let color = vertex.color().unwrap_or(Vec4::ONE);
+        ClipVertex::with_varyings(
+            clip_from_local * vertex.position().extend(1.0),
+            Varyings::new(&color.to_array()),
+        )
+    }
+}
+
+/// A fragment produced by the rasterizer
+#[derive(Clone, Copy, Debug)]
+pub struct Fragment {
+    /// Pixel coordinate on the canvas
+    pub pos: UVec2,
+    pub depth: f32,
+    pub varyings: Varyings,
+    /// Screen space derivatives of the varyings along x.
+    ///
+    /// Fragments are shaded in 2x2 quads and the derivatives are the difference between the
+    /// fragments of the quad, so they're the same for the whole quad.
+    pub ddx: Varyings,
+    /// Screen space derivatives of the varyings along y, see [`Fragment::ddx`]
+    pub ddy: Varyings,
+}
+
+/// 8 fragments shaded at once by the wide rasterizers.
+///
+/// Lanes 0 to 3 are a row of 4 pixels and lanes 4 to 7 are the row below, so the lanes form 2
+/// quads of 2x2 pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct FragmentX8 {
+    /// Pixel coordinates on the canvas
+    pub pos: Vec2x8,
+    pub depth: f32x8,
+    pub varyings: VaryingsX8,
+    /// See [`Fragment::ddx`]
+    pub ddx: VaryingsX8,
+    /// See [`Fragment::ddy`]
+    pub ddy: VaryingsX8,
+    /// Lanes that are covered by the triangle and passed the depth test. Shaders can clear a lane
+    /// to discard it.
+    pub mask: [bool; 8],
+}
+
+impl FragmentX8 {
+    /// Extracts a single lane
+    pub fn lane(&self, lane: usize) -> Fragment {
+        let pos: [Vec2; 8] = self.pos.into();
+        Fragment {
+            pos: pos[lane].as_uvec2(),
+            depth: self.depth.to_array()[lane],
+            varyings: self.varyings.lane(lane),
+            ddx: self.ddx.lane(lane),
+            ddy: self.ddy.lane(lane),
+        }
+    }
+}
+
+/// Linear rgba colors of 8 fragments
+#[derive(Clone, Copy, Debug)]
+pub struct ColorX8 {
+    pub rgb: Vec3x8,
+    pub alpha: f32x8,
+}
+
+/// Programmable fragment stage of the rasterizers
+pub trait FragmentShader {
+    /// Returns the linear rgba color of the fragment, or `None` to discard it
+    fn shade(&self, fragment: &Fragment) -> Option<Vec4>;
+
+    /// Shades 8 fragments at once. Lanes that aren't in `fragment.mask` are ignored, clear a lane
+    /// of the mask to discard it.
+    ///
+    /// The default implementation calls [`FragmentShader::shade`] for each lane. Implement this
+    /// with SIMD types to keep the wide rasterizers fast.
+    fn shade_x8(&self, fragment: &mut FragmentX8) -> ColorX8 {
+        let mut colors = [Vec4::ZERO; 8];
+        for (lane, color) in colors.iter_mut().enumerate() {
+            if !fragment.mask[lane] {
+                continue;
+            }
+            match self.shade(&fragment.lane(lane)) {
+                Some(c) => *color = c,
+                None => fragment.mask[lane] = false,
+            }
+        }
+        ColorX8 {
+            rgb: Vec3x8::new(
+                f32x8::new(colors.map(|c| c.x)),
+                f32x8::new(colors.map(|c| c.y)),
+                f32x8::new(colors.map(|c| c.z)),
+            ),
+            alpha: f32x8::new(colors.map(|c| c.w)),
+        }
+    }
+}
+
+/// Outputs the linear rgba color stored in the first 4 varyings, see [`Varyings::from_color`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VertexColor;
+
+impl FragmentShader for VertexColor {
+    #[inline(always)]
+    fn shade(&self, fragment: &Fragment) -> Option<Vec4> {
+        Some(fragment.varyings.vec4(0))
+    }
+
+    #[inline(always)]
+    fn shade_x8(&self, fragment: &mut FragmentX8) -> ColorX8 {
+        let varyings = &fragment.varyings;
+        ColorX8 {
+            rgb: Vec3x8::new(varyings.get(0), varyings.get(1), varyings.get(2)),
+            alpha: varyings.get(3),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use bevy::prelude::*;
+use wgpu::{AddressMode, FilterMode};
+
+use crate::format::{MAX_PIXEL_SIZE, PixelFormat};
+
+/// Maximum number of mip levels of a [`Texture`], enough for a 32k texture
+pub const MAX_MIP_LEVELS: usize = 16;
+
+/// Describes how a [`Texture`] is sampled, similar to a gpu sampler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampler {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    /// Filter used when the texture is magnified, when the lod is 0 or less
+    pub mag_filter: FilterMode,
+    /// Filter used when the texture is minified
+    pub min_filter: FilterMode,
+    /// Filter used between mip levels
+    pub mipmap_filter: FilterMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+}
+
+impl Sampler {
+    /// Same defaults as wgpu
+    pub const DEFAULT: Self = Self {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        mipmap_filter: FilterMode::Nearest,
+        lod_min_clamp: 0.0,
+        lod_max_clamp: 32.0,
+    };
+
+    pub const NEAREST: Self = Self::DEFAULT;
+
+    /// Bilinear filtering without blending between mip levels
+    pub const BILINEAR: Self = Self {
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..Self::DEFAULT
+    };
+
+    /// Trilinear filtering
+    pub const LINEAR: Self = Self {
+        mipmap_filter: FilterMode::Linear,
+        ..Self::BILINEAR
+    };
+
+    /// Uses the same address mode on both axis
+    pub const fn with_address_mode(self, address_mode: AddressMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            ..self
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Read only view of an [`Image`] that can be sampled by a fragment shader.
+///
+/// Supports the same formats as the canvas, see [`PixelFormat`]. Every value returned is linear,
+/// sRGB textures are decoded before filtering.
+///
+/// The mip levels of the image are used when it has any, see [`generate_mipmaps`].
+#[derive(Clone, Copy)]
+pub struct Texture<'a> {
+    data: &'a [u8],
+    size: UVec2,
+    format: PixelFormat,
+    mip_level_count: usize,
+    /// Offset of each mip level in `data`
+    mip_offsets: [usize; MAX_MIP_LEVELS],
+}
+
+impl<'a> Texture<'a> {
+    /// Returns `None` if the image is empty or if its format isn't supported.
+    pub fn new(image: &'a Image) -> Option<Self> {
+        let format = PixelFormat::new(image.texture_descriptor.format)?;
+        let size = image.size();
+        if size.x == 0 || size.y == 0 {
+            return None;
+        }
+        let data = image.data.as_deref()?;
+
+        let mip_level_count =
+            (image.texture_descriptor.mip_level_count as usize).clamp(1, MAX_MIP_LEVELS);
+        let mut mip_offsets = [0; MAX_MIP_LEVELS];
+        let mut offset = 0;
+        for (level, mip_offset) in mip_offsets.iter_mut().enumerate().take(mip_level_count) {
+            *mip_offset = offset;
+            let level_size = mip_size(size, level);
+            offset += (level_size.x * level_size.y) as usize * format.size();
+        }
+        if data.len() < offset {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            size,
+            format,
+            mip_level_count,
+            mip_offsets,
+        })
+    }
+
+    /// Size of the first mip level
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn mip_level_count(&self) -> usize {
+        self.mip_level_count
+    }
+
+    /// Returns the linear rgba value of a texel of a mip level without any filtering.
+    ///
+    /// Missing channels are 0 and a missing alpha is 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the level or the texel is outside of the texture.
+    #[inline(always)]
+    pub fn load(&self, texel: UVec2, level: usize) -> Vec4 {
+        assert!(level < self.mip_level_count);
+        let size = mip_size(self.size, level);
+        assert!(texel.x < size.x && texel.y < size.y);
+        let pixel_size = self.format.size();
+        let offset = self.mip_offsets[level] + (texel.y * size.x + texel.x) as usize * pixel_size;
+        self.format.decode(&self.data[offset..offset + pixel_size])
+    }
+
+    /// Samples the first mip level at the given uv, `(0, 0)` is the top left corner of the
+    /// texture.
+    ///
+    /// Texels outside of the texture with [`AddressMode::ClampToBorder`] are transparent black.
+    pub fn sample(&self, sampler: &Sampler, uv: Vec2) -> Vec4 {
+        self.sample_level(sampler, uv, 0.0)
+    }
+
+    /// Samples the texture using the screen space derivatives of the uv to select the mip
+    /// level, like `textureSampleGrad` in wgsl.
+    ///
+    /// The derivatives of the varyings are available in [`Fragment::ddx`] and
+    /// [`Fragment::ddy`](crate::shader::Fragment::ddy).
+    ///
+    /// [`Fragment::ddx`]: crate::shader::Fragment::ddx
+    pub fn sample_grad(&self, sampler: &Sampler, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        let size = self.size.as_vec2();
+        let footprint = (ddx * size).length().max((ddy * size).length());
+        self.sample_level(sampler, uv, footprint.log2())
+    }
+
+    /// Samples the texture at an explicit level of detail, like `textureSampleLevel` in wgsl.
+    pub fn sample_level(&self, sampler: &Sampler, uv: Vec2, lod: f32) -> Vec4 {
+        let lod = lod.clamp(sampler.lod_min_clamp, sampler.lod_max_clamp);
+        if lod <= 0.0 {
+            return self.sample_mip(sampler, sampler.mag_filter, uv, 0);
+        }
+        let lod = lod.min((self.mip_level_count - 1) as f32);
+        match sampler.mipmap_filter {
+            FilterMode::Nearest => {
+                self.sample_mip(sampler, sampler.min_filter, uv, lod.round() as usize)
+            }
+            FilterMode::Linear => {
+                let level = lod.floor();
+                let t = lod - level;
+                let level = level as usize;
+                let color = self.sample_mip(sampler, sampler.min_filter, uv, level);
+                if t == 0.0 {
+                    return color;
+                }
+                color.lerp(
+                    self.sample_mip(sampler, sampler.min_filter, uv, level + 1),
+                    t,
+                )
+            }
+        }
+    }
+
+    /// Samples a single mip level
+    #[inline(always)]
+    fn sample_mip(&self, sampler: &Sampler, filter: FilterMode, uv: Vec2, level: usize) -> Vec4 {
+        let pos = uv * mip_size(self.size, level).as_vec2();
+        match filter {
+            FilterMode::Nearest => self.fetch(sampler, pos.floor().as_ivec2(), level),
+            FilterMode::Linear => {
+                // Texel centers are at half coordinates
+                let pos = pos - 0.5;
+                let texel = pos.floor();
+                let t = pos - texel;
+                let texel = texel.as_ivec2();
+                // Huge coordinates saturate, so the neighbours must too
+                let neighbour = |offset| texel.saturating_add(offset);
+                let top = self
+                    .fetch(sampler, texel, level)
+                    .lerp(self.fetch(sampler, neighbour(IVec2::X), level), t.x);
+                let bottom = self
+                    .fetch(sampler, neighbour(IVec2::Y), level)
+                    .lerp(self.fetch(sampler, neighbour(IVec2::ONE), level), t.x);
+                top.lerp(bottom, t.y)
+            }
+        }
+    }
+
+    /// Applies the address modes and loads the texel
+    #[inline(always)]
+    fn fetch(&self, sampler: &Sampler, texel: IVec2, level: usize) -> Vec4 {
+        let size = mip_size(self.size, level);
+        let x = address(sampler.address_mode_u, texel.x, size.x);
+        let y = address(sampler.address_mode_v, texel.y, size.y);
+        match (x, y) {
+            (Some(x), Some(y)) => self.load(UVec2::new(x, y), level),
+            _ => Vec4::ZERO,
+        }
+    }
+}
+
+/// Generates the full mip chain of an image with a box filter and stores it in the image after
+/// the first level, like wgpu expects it.
+///
+/// The filtering is done in linear space so sRGB images keep the right brightness. Any existing
+/// mip level is replaced.
+pub fn generate_mipmaps(image: &mut Image) {
+    let Some(texture) = Texture::new(image) else {
+        warn!("Can't generate mipmaps of an empty image or an unsupported format");
+        return;
+    };
+    let format = texture.format;
+    let size = texture.size;
+    let mip_level_count = (size.x.max(size.y).ilog2() as usize + 1).min(MAX_MIP_LEVELS);
+
+    let mut level: Vec<Vec4> = (0..size.y)
+        .flat_map(|y| (0..size.x).map(move |x| UVec2::new(x, y)))
+        .map(|texel| texture.load(texel, 0))
+        .collect();
+    let mut data = texture.data[..level.len() * format.size()].to_vec();
+
+    for mip in 1..mip_level_count {
+        let src_size = mip_size(size, mip - 1);
+        let dst_size = mip_size(size, mip);
+        let src = |x: u32, y: u32| {
+            let x = x.min(src_size.x - 1);
+            let y = y.min(src_size.y - 1);
+            level[(y * src_size.x + x) as usize]
+        };
+        let mut next = Vec::with_capacity((dst_size.x * dst_size.y) as usize);
+        for y in 0..dst_size.y {
+            for x in 0..dst_size.x {
+                let color = src(x * 2, y * 2)
+                    + src(x * 2 + 1, y * 2)
+                    + src(x * 2, y * 2 + 1)
+                    + src(x * 2 + 1, y * 2 + 1);
+                next.push(color * 0.25);
+            }
+        }
+        let mut pixel = [0; MAX_PIXEL_SIZE];
+        for color in &next {
+            format.encode(*color, &mut pixel);
+            data.extend_from_slice(&pixel[..format.size()]);
+        }
+        level = next;
+    }
+
+    image.data = Some(data);
+    image.texture_descriptor.mip_level_count = mip_level_count as u32;
+}
+
+#[inline(always)]
+fn mip_size(size: UVec2, level: usize) -> UVec2 {
+    (size >> level as u32).max(UVec2::ONE)
+}
+
+/// Maps a texel coordinate inside of the texture, `None` means the border should be used.
+#[inline(always)]
+fn address(mode: AddressMode, coord: i32, size: u32) -> Option<u32> {
+    let size = size as i32;
+    let coord = match mode {
+        AddressMode::ClampToEdge => coord.clamp(0, size - 1),
+        AddressMode::Repeat => coord.rem_euclid(size),
+        AddressMode::MirrorRepeat => {
+            let coord = coord.rem_euclid(size * 2);
+            if coord >= size {
+                size * 2 - 1 - coord
+            } else {
+                coord
+            }
+        }
+        AddressMode::ClampToBorder => {
+            if !(0..size).contains(&coord) {
+                return None;
+            }
+            coord
+        }
+    };
+    Some(coord as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::RenderAssetUsages;
+    use wgpu::{Extent3d, TextureDimension, TextureFormat};
+
+    use super::*;
+
+    #[test]
+    fn address_modes() {
+        let coords = [-5, -1, 0, 3, 4, 7, 9];
+        let address = |mode| coords.map(|coord| address(mode, coord, 4));
+        assert_eq!(
+            address(AddressMode::ClampToEdge),
+            [0, 0, 0, 3, 3, 3, 3].map(Some)
+        );
+        assert_eq!(
+            address(AddressMode::Repeat),
+            [3, 3, 0, 3, 0, 3, 1].map(Some)
+        );
+        assert_eq!(
+            address(AddressMode::MirrorRepeat),
+            [3, 0, 0, 3, 3, 0, 1].map(Some)
+        );
+        assert_eq!(
+            address(AddressMode::ClampToBorder),
+            [None, None, Some(0), Some(3), None, None, None]
+        );
+    }
+
+    #[test]
+    fn huge_uvs_are_sampled() {
+        let image = Image::new_fill(
+            Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[255, 0, 0, 255],
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::all(),
+        );
+        let texture = Texture::new(&image).unwrap();
+        for address_mode in [
+            AddressMode::ClampToEdge,
+            AddressMode::Repeat,
+            AddressMode::MirrorRepeat,
+        ] {
+            let sampler = Sampler::LINEAR.with_address_mode(address_mode);
+            for uv in [Vec2::new(1e12, 0.5), Vec2::new(-1e12, 1e12)] {
+                assert_eq!(texture.sample(&sampler, uv), Vec4::new(1.0, 0.0, 0.0, 1.0));
+            }
+        }
+    }
+}
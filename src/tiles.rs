@@ -0,0 +1,163 @@
+//! Sort-middle parallel rasterization, see [`GlaciersCanvas::draw_triangles_parallel`].
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
+
+use crate::{
+    canvas::{DrawState, GlaciersCanvas, Primitive, Triangle, TriangleSetup},
+    shader::FragmentShader,
+};
+
+/// Size in pixels of the square tiles triangles are binned into. Each row of tiles is a band
+/// of the canvas drawn by a single task.
+pub const TILE_SIZE: u32 = 64;
+
+impl GlaciersCanvas<'_> {
+    /// Draws triangles in canvas space using every thread of the [`ComputeTaskPool`].
+    ///
+    /// The triangles are first set up like in [`GlaciersCanvas::draw_triangles`] and binned into
+    /// tiles of [`TILE_SIZE`] pixels, then each row of tiles is rasterized by its own task. Tasks
+    /// own disjoint rows of the buffers and every tile draws its triangles in submission order,
+    /// so the result is identical to drawing the triangles one by one.
+    pub fn draw_triangles_parallel(
+        &mut self,
+        triangles: &[Triangle],
+        state: &DrawState,
+        shader: &(impl FragmentShader + Sync),
+    ) {
+        let _draw_triangles_parallel_span = info_span!("draw_triangles_parallel").entered();
+        if self.scissor.is_empty() {
+            return;
+        }
+        let tiles = (self.size + TILE_SIZE - 1) / TILE_SIZE;
+
+        // Setup of the visible triangles, done once for every tile they overlap
+        let mut setups = Vec::with_capacity(triangles.len());
+        for chunk in triangles.chunks(8) {
+            setups.extend(
+                TriangleSetup::new_x8(self, chunk, state)
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+
+        // Indices of the setups overlapping each tile, in submission order
+        let mut bins = vec![Vec::new(); (tiles.x * tiles.y) as usize];
+        for (i, setup) in setups.iter().enumerate() {
+            let min_tile = setup.min.xy().as_uvec2() / TILE_SIZE;
+            let max_tile = setup.max.xy().as_uvec2() / TILE_SIZE;
+            for y in min_tile.y..=max_tile.y {
+                for x in min_tile.x..=max_tile.x {
+                    bins[(y * tiles.x + x) as usize].push(i as u32);
+                }
+            }
+        }
+
+        let (setups, scissor) = (&setups, self.scissor);
+        let task_pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        task_pool.scope(|scope| {
+            let bands = self.split_bands();
+            for (mut band, bins) in bands.into_iter().zip(bins.chunks(tiles.x as usize)) {
+                if bins.iter().all(Vec::is_empty) {
+                    continue;
+                }
+                scope.spawn(async move {
+                    let y = band.rows.start;
+                    for (x, bin) in bins.iter().enumerate() {
+                        if bin.is_empty() {
+                            continue;
+                        }
+                        let tile_min = UVec2::new(x as u32 * TILE_SIZE, y);
+                        let tile = URect::from_corners(tile_min, tile_min + TILE_SIZE);
+                        band.scissor = tile.intersect(scissor);
+                        for &i in bin {
+                            band.rasterize_wide(&setups[i as usize], state, shader);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Splits the canvas in bands of [`TILE_SIZE`] rows that can be drawn to in parallel. Bands
+    /// only own their rows of the buffers but use the coordinates of the whole canvas.
+    fn split_bands(&mut self) -> Vec<GlaciersCanvas<'_>> {
+        let band_pixels = (TILE_SIZE * self.size.x) as usize;
+        let samples = self.msaa.samples() as usize;
+        let colors = self.color.chunks_mut(band_pixels * self.format.size());
+        let depths = self.depth.chunks_mut(band_pixels * samples);
+        let mut sample_bands = self
+            .samples
+            .as_deref_mut()
+            .map(|data| data.chunks_mut(band_pixels * samples * self.sample_format.size()));
+
+        colors
+            .zip(depths)
+            .enumerate()
+            .map(|(i, (color, depth))| {
+                let start = i as u32 * TILE_SIZE;
+                let rows: Range<u32> = start..(start + TILE_SIZE).min(self.size.y);
+                GlaciersCanvas {
+                    color,
+                    depth,
+                    samples: sample_bands.as_mut().and_then(Iterator::next),
+                    size: self.size,
+                    scissor: URect::new(0, rows.start, self.size.x, rows.end)
+                        .intersect(self.scissor),
+                    rows,
+                    msaa: self.msaa,
+                    depth_clear_value: self.depth_clear_value,
+                    sample_offsets: self.sample_offsets,
+                    format: self.format,
+                    sample_format: self.sample_format,
+                    tonemapping: self.tonemapping,
+                    exposure: self.exposure,
+                    viewport: self.viewport.clone(),
+                    rasterizer: self.rasterizer,
+                    assert_bounds: self.assert_bounds,
+                    show_box_outlines: self.show_box_outlines,
+                    primitive: Primitive::None,
+                    resolve_on_drop: false,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        canvas::tests::{TestCanvas, random_triangles},
+        shader::VertexColor,
+    };
+
+    #[test]
+    fn parallel_matches_sequential() {
+        // Not a multiple of the tile size so the last row and column of tiles are partial
+        let size = UVec2::new(157, 141);
+        let triangles = random_triangles(1, size, 200);
+        let state = DrawState {
+            cull_mode: None,
+            ..default()
+        };
+        for msaa in [Msaa::Off, Msaa::Sample4] {
+            let mut sequential = TestCanvas::new(size, msaa);
+            let mut canvas = sequential.canvas();
+            for triangle in &triangles {
+                canvas.draw_triangle(triangle, &state, &VertexColor);
+            }
+            drop(canvas);
+            let mut parallel = TestCanvas::new(size, msaa);
+            parallel
+                .canvas()
+                .draw_triangles_parallel(&triangles, &state, &VertexColor);
+            assert!(sequential.color.iter().any(|&byte| byte != 0));
+            assert!(sequential.color == parallel.color, "{msaa:?}");
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Tonemapping of high dynamic range canvases, see [`TonemapOperator`].
+
+use bevy::prelude::*;
+
+/// Converts linear sRGB to the ACES AP1 space with the RRT saturation applied
+const ACES_INPUT: Mat3 = Mat3::from_cols(
+    Vec3::new(0.59719, 0.07600, 0.02840),
+    Vec3::new(0.35458, 0.90834, 0.13383),
+    Vec3::new(0.04823, 0.01566, 0.83777),
+);
+
+/// Converts the output of the ODT back to linear sRGB
+const ACES_OUTPUT: Mat3 = Mat3::from_cols(
+    Vec3::new(1.60475, -0.10208, -0.00327),
+    Vec3::new(-0.53108, 1.10813, -0.07276),
+    Vec3::new(-0.07367, -0.00605, 1.07602),
+);
+
+/// Converts linear sRGB to the AgX working space
+const AGX_INPUT: Mat3 = Mat3::from_cols(
+    Vec3::new(0.84247906, 0.042328242, 0.042375655),
+    Vec3::new(0.0784336, 0.87846864, 0.0784336),
+    Vec3::new(0.079223745, 0.07916613, 0.879143),
+);
+
+/// Inverse of [`AGX_INPUT`]
+const AGX_OUTPUT: Mat3 = Mat3::from_cols(
+    Vec3::new(1.196879, -0.052896852, -0.052971636),
+    Vec3::new(-0.09802088, 1.1519031, -0.09804345),
+    Vec3::new(-0.09902974, -0.098961177, 1.1510737),
+);
+
+/// Range of exposure values in stops around middle gray that AgX maps to the display
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+/// Operator used to map the colors of a high dynamic range canvas to the displayed image.
+///
+/// Bevy's own tonemapping doesn't apply to the canvas since it's copied after it, so the canvas
+/// is tonemapped on the cpu when it's resolved, see
+/// [`GlaciersContext::tonemapping`](crate::GlaciersContext::tonemapping).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// Colors are clamped to the 0-1 range
+    #[default]
+    None,
+    /// Simple curve applied to each channel, desaturates bright colors
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference rendering transform. High contrast and saturated.
+    AcesFitted,
+    /// Blender's AgX with the minimal polynomial fit by Benjamin Wrensch. Bright colors smoothly
+    /// shift towards white.
+    AgX,
+}
+
+impl TonemapOperator {
+    /// Maps a linear color to the 0-1 range, the result is still linear.
+    pub fn apply(self, color: Vec3) -> Vec3 {
+        let color = color.max(Vec3::ZERO);
+        match self {
+            TonemapOperator::None => color.min(Vec3::ONE),
+            TonemapOperator::Reinhard => color / (color + 1.0),
+            TonemapOperator::AcesFitted => {
+                let v = ACES_INPUT * color;
+                let v = (v * (v + 0.0245786) - 0.000090537)
+                    / (v * (0.983729 * v + 0.432951) + 0.238081);
+                (ACES_OUTPUT * v).clamp(Vec3::ZERO, Vec3::ONE)
+            }
+            TonemapOperator::AgX => {
+                let v = AGX_INPUT * color;
+                // Log encoding, the small minimum avoids the log of 0
+                let v = v.max(Vec3::splat(1e-10)).map(f32::log2);
+                let x = (v.clamp(Vec3::splat(AGX_MIN_EV), Vec3::splat(AGX_MAX_EV)) - AGX_MIN_EV)
+                    / (AGX_MAX_EV - AGX_MIN_EV);
+                // Polynomial fit of the default AgX contrast curve
+                let x2 = x * x;
+                let x4 = x2 * x2;
+                let v = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+                    + 0.4298 * x2
+                    + 0.1191 * x
+                    - 0.00232;
+                // The curve outputs display encoded values, 2.2 is what AgX uses to linearize them
+                (AGX_OUTPUT * v).max(Vec3::ZERO).powf(2.2).min(Vec3::ONE)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [TonemapOperator; 4] = [
+        TonemapOperator::None,
+        TonemapOperator::Reinhard,
+        TonemapOperator::AcesFitted,
+        TonemapOperator::AgX,
+    ];
+
+    #[test]
+    fn operators_map_to_the_unit_range() {
+        for operator in OPERATORS {
+            assert!(
+                operator.apply(Vec3::ZERO).abs_diff_eq(Vec3::ZERO, 1e-3),
+                "{operator:?}"
+            );
+            assert_eq!(
+                operator.apply(Vec3::splat(-1.0)),
+                operator.apply(Vec3::ZERO),
+                "{operator:?}"
+            );
+            // Brighter greys never get darker
+            let mut previous = operator.apply(Vec3::ZERO);
+            for i in 1..=100 {
+                let color = operator.apply(Vec3::splat(i as f32 * 0.25));
+                assert!(color.cmpge(Vec3::ZERO).all() && color.cmple(Vec3::ONE).all());
+                assert!(color.cmpge(previous - 1e-5).all(), "{operator:?} {i}");
+                previous = color;
+            }
+            let saturated = operator.apply(Vec3::new(100.0, 0.0, 0.5));
+            assert!(saturated.cmpge(Vec3::ZERO).all() && saturated.cmple(Vec3::ONE).all());
+        }
+    }
+
+    #[test]
+    fn operator_curves() {
+        assert_eq!(
+            TonemapOperator::None.apply(Vec3::new(0.5, 2.0, -1.0)),
+            Vec3::new(0.5, 1.0, 0.0)
+        );
+        assert_eq!(
+            TonemapOperator::Reinhard.apply(Vec3::new(1.0, 3.0, 0.0)),
+            Vec3::new(0.5, 0.75, 0.0)
+        );
+        // The filmic curves keep middle grey dark and bring bright greys close to white
+        for operator in [TonemapOperator::AcesFitted, TonemapOperator::AgX] {
+            let grey = operator.apply(Vec3::splat(0.18));
+            assert!(
+                grey.min_element() > 0.05 && grey.max_element() < 0.5,
+                "{operator:?}"
+            );
+            let white = operator.apply(Vec3::splat(16.0));
+            assert!(white.min_element() > 0.85, "{operator:?} {white}");
+        }
+    }
+}
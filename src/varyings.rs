@@ -0,0 +1,178 @@
+use std::ops::Sub;
+
+use bevy::prelude::*;
+use glam_wide::f32x8;
+
+use crate::interpolation::Lanes;
+
+/// Maximum number of floats a vertex can pass to the fragment stage.
+pub const MAX_VARYINGS: usize = 16;
+
+/// Per vertex data interpolated across a triangle and handed to the fragment stage, things like
+/// colors, uvs, normals or world positions.
+///
+/// This is a fixed capacity float array, it's up to the user to decide how the floats are laid
+/// out. [`Varyings::from_color`] stores a linear rgba color in the first 4 floats, which is what
+/// [`Vertex::new`](crate::canvas::Vertex::new) uses.
+///
+/// `T` is [`f32`] for a single fragment or [`f32x8`] when 8 fragments are interpolated at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Varyings<T = f32> {
+    data: [T; MAX_VARYINGS],
+    len: usize,
+}
+
+/// Varyings of 8 fragments, one per SIMD lane.
+pub type VaryingsX8 = Varyings<f32x8>;
+
+impl Varyings<f32> {
+    pub const EMPTY: Self = Self {
+        data: [0.0; MAX_VARYINGS],
+        len: 0,
+    };
+
+    /// # Panics
+    ///
+    /// Panics if there's more than [`MAX_VARYINGS`] values.
+    pub fn new(values: &[f32]) -> Self {
+        let mut varyings = Self::EMPTY;
+        varyings.extend_from_slice(values);
+        varyings
+    }
+
+    /// Stores the color as linear rgba.
+    pub fn from_color(color: Color) -> Self {
+        Self::new(&color.to_linear().to_f32_array())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the varyings are full.
+    pub fn push(&mut self, value: f32) {
+        assert!(self.len < MAX_VARYINGS, "Too many varyings");
+        self.data[self.len] = value;
+        self.len += 1;
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the values don't fit.
+    pub fn extend_from_slice(&mut self, values: &[f32]) {
+        assert!(self.len + values.len() <= MAX_VARYINGS, "Too many varyings");
+        self.data[self.len..self.len + values.len()].copy_from_slice(values);
+        self.len += values.len();
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data[..self.len]
+    }
+
+    pub fn vec2(&self, offset: usize) -> Vec2 {
+        Vec2::from_slice(&self.data[offset..])
+    }
+
+    pub fn vec3(&self, offset: usize) -> Vec3 {
+        Vec3::from_slice(&self.data[offset..])
+    }
+
+    pub fn vec4(&self, offset: usize) -> Vec4 {
+        Vec4::from_slice(&self.data[offset..])
+    }
+
+    /// Linear interpolation used when clipping
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut result = *self;
+        for i in 0..self.len {
+            result.data[i] = self.data[i] + (other.data[i] - self.data[i]) * t;
+        }
+        result
+    }
+}
+
+impl<T: Lanes> Varyings<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Values past [`Varyings::len`] are 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is bigger or equal to [`MAX_VARYINGS`].
+    #[inline(always)]
+    pub fn get(&self, index: usize) -> T {
+        self.data[index]
+    }
+
+    /// Interpolates the varyings of the 3 vertices of a triangle using the given weights.
+    ///
+    /// Only the varyings used by every vertex are interpolated.
+    #[inline(always)]
+    pub(crate) fn interpolate(vertices: [&Varyings; 3], weights: [T; 3]) -> Self {
+        let len = vertices[0].len.min(vertices[1].len).min(vertices[2].len);
+        let mut data = [T::splat(0.0); MAX_VARYINGS];
+        for (i, value) in data.iter_mut().enumerate().take(len) {
+            *value = T::splat(vertices[0].data[i]) * weights[0]
+                + T::splat(vertices[1].data[i]) * weights[1]
+                + T::splat(vertices[2].data[i]) * weights[2];
+        }
+        Self { data, len }
+    }
+}
+
+impl VaryingsX8 {
+    /// Extracts the varyings of a single lane
+    pub fn lane(&self, lane: usize) -> Varyings {
+        let mut varyings = Varyings::EMPTY;
+        for i in 0..self.len {
+            varyings.data[i] = self.data[i].to_array()[lane];
+        }
+        varyings.len = self.len;
+        varyings
+    }
+
+    /// Returns the coarse `(ddx, ddy)` derivatives of the fragments, assuming the lanes are laid
+    /// out like [`FragmentX8`](crate::shader::FragmentX8), as 2 quads of 2x2 pixels.
+    ///
+    /// Every lane of a quad gets the same derivatives.
+    #[inline(always)]
+    pub(crate) fn quad_derivatives(&self) -> (Self, Self) {
+        let mut ddx = Self {
+            data: [f32x8::splat(0.0); MAX_VARYINGS],
+            len: self.len,
+        };
+        let mut ddy = ddx;
+        for i in 0..self.len {
+            let v = self.data[i].to_array();
+            let dx = [v[1] - v[0], v[3] - v[2]];
+            let dy = [v[4] - v[0], v[6] - v[2]];
+            ddx.data[i] = f32x8::new([dx[0], dx[0], dx[1], dx[1], dx[0], dx[0], dx[1], dx[1]]);
+            ddy.data[i] = f32x8::new([dy[0], dy[0], dy[1], dy[1], dy[0], dy[0], dy[1], dy[1]]);
+        }
+        (ddx, ddy)
+    }
+}
+
+impl<T: Lanes> Sub for Varyings<T> {
+    type Output = Self;
+
+    /// Only the varyings used by both sides are subtracted
+    fn sub(self, rhs: Self) -> Self {
+        let len = self.len.min(rhs.len);
+        let mut data = [T::splat(0.0); MAX_VARYINGS];
+        for (i, value) in data.iter_mut().enumerate().take(len) {
+            *value = self.data[i] - rhs.data[i];
+        }
+        Self { data, len }
+    }
+}
+
+impl Default for Varyings {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}